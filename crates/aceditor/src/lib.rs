@@ -45,6 +45,12 @@ mod bindgen {
 
         #[wasm_bindgen(method, js_name = setReadOnly)]
         pub fn set_read_only(this: &Editor, value: bool);
+
+        #[wasm_bindgen(method)]
+        pub fn insert(this: &Editor, text: &str);
+
+        #[wasm_bindgen(method)]
+        pub fn focus(this: &Editor);
     }
 
     #[wasm_bindgen]
@@ -273,6 +279,12 @@ impl Editor {
         self.js.set_read_only(value);
     }
 
+    /// Insert text at the cursor, replacing the selection
+    pub fn insert(&self, text: &str) {
+        self.js.insert(text);
+        self.js.focus();
+    }
+
     pub fn get_length(&self) -> usize {
         self.js.get_session().get_length()
     }
@@ -1,15 +1,29 @@
 use leptos::prelude::*;
 use reactive_stores::Store;
+use web_sys::Event;
 
 use crate::app::{
-    GlobalState, GlobalStateStoreFields, config_element::Either, menu_aside::MenuAside,
+    GlobalState, GlobalStateStoreFields,
+    config_element::{Either, Select},
+    default_snapshot_limit,
+    encryption_menu::EncryptionMenu,
+    menu_aside::MenuAside,
     menu_group::MenuGroup,
 };
 
+/// Choices of the snapshot memory in MiB.
+const SNAPSHOT_LIMITS: [u32; 4] = [0, 16, 64, 256];
+
 #[component]
 pub fn AdvancedOptionsMenu() -> impl IntoView {
     let state = expect_context::<Store<GlobalState>>();
 
+    let snapshot_limit_change = move |event: Event| {
+        if let Ok(limit) = event_target_value(&event).parse() {
+            state.snapshot_limit().set(limit);
+        }
+    };
+
     view! {
         <>
             <MenuGroup title="Advanced options".into()>
@@ -26,6 +40,27 @@ pub fn AdvancedOptionsMenu() -> impl IntoView {
                         state.run_selected_sql().set(*value);
                     }
                 />
+                <Either
+                    aside=Some(
+                        view! {
+                            <MenuAside>
+                                "Failed statements are shown in the output and the following statements keep running."
+                            </MenuAside>
+                        }
+                            .into_any(),
+                    )
+                    id="continue_on_error".into()
+                    name="Continue On Error".into()
+                    a=true
+                    b=false
+                    a_label=Some("On".to_string())
+                    b_label=Some("Off".to_string())
+                    value=move || *state.continue_on_error().read()
+                    is_default=Box::new(move || !*state.continue_on_error().read())
+                    on_change=move |value: &bool| {
+                        state.continue_on_error().set(*value);
+                    }
+                />
                 <Either
                     aside=Some(
                         view! {
@@ -49,7 +84,38 @@ pub fn AdvancedOptionsMenu() -> impl IntoView {
                         state.multiple_ciphers().set(*value);
                     }
                 />
+                <Select
+                    name="Snapshot Memory".into()
+                    is_default=Box::new(move || {
+                        *state.snapshot_limit().read() == default_snapshot_limit()
+                    })
+                    on_change=snapshot_limit_change
+                >
+                    {move || {
+                        SNAPSHOT_LIMITS
+                            .into_iter()
+                            .map(|limit| {
+                                let label = if limit == 0 {
+                                    "Off".to_string()
+                                } else {
+                                    format!("{limit} MiB")
+                                };
+                                view! {
+                                    <option
+                                        value=limit.to_string()
+                                        selected=limit == *state.snapshot_limit().read()
+                                    >
+                                        {label}
+                                    </option>
+                                }
+                            })
+                            .collect_view()
+                    }}
+                </Select>
             </MenuGroup>
+            <Show when=move || *state.multiple_ciphers().read() fallback=|| ()>
+                <EncryptionMenu />
+            </Show>
         </>
     }
 }
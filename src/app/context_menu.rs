@@ -1,8 +1,9 @@
 use leptos::prelude::*;
 use reactive_stores::Store;
 
-use crate::app::{
-    GlobalState, GlobalStateStoreFields, menu_group::MenuGroup, select_one::SelectOne,
+use crate::{
+    RunMode,
+    app::{GlobalState, GlobalStateStoreFields, menu_group::MenuGroup, select_one::SelectOne},
 };
 
 #[component]
@@ -12,25 +13,35 @@ pub fn ContextMenu() -> impl IntoView {
     view! {
         <MenuGroup title="Choose whether to keep the context".into()>
             <SelectOne
-                name="Drop Context".into()
-                current_value=move || { *state.keep_ctx().read() }
-                this_value=false
+                name=RunMode::DropContext.value().into()
+                current_value=move || { *state.run_mode().read() }
+                this_value=RunMode::DropContext
                 change_value=move || {
-                    state.keep_ctx().set(false);
+                    state.run_mode().set(RunMode::DropContext);
                 }
             >
                 "Each execution is in a new DB."
             </SelectOne>
             <SelectOne
-                name="Keep Context".into()
-                current_value=move || { *state.keep_ctx().read() }
-                this_value=true
+                name=RunMode::KeepContext.value().into()
+                current_value=move || { *state.run_mode().read() }
+                this_value=RunMode::KeepContext
                 change_value=move || {
-                    state.keep_ctx().set(true);
+                    state.run_mode().set(RunMode::KeepContext);
                 }
             >
                 "Keep the results of each execution."
             </SelectOne>
+            <SelectOne
+                name=RunMode::DryRun.value().into()
+                current_value=move || { *state.run_mode().read() }
+                this_value=RunMode::DryRun
+                change_value=move || {
+                    state.run_mode().set(RunMode::DryRun);
+                }
+            >
+                "Execute on the current DB and roll back all changes. Transaction statements are not allowed."
+            </SelectOne>
         </MenuGroup>
     }
 }
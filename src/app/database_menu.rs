@@ -1,21 +1,398 @@
 use leptos::prelude::*;
-use web_sys::MouseEvent;
+use reactive_stores::Store;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{Event, MouseEvent};
 
-use crate::app::{button_menu_item::ButtonMenuItem, menu_aside::MenuAside, menu_group::MenuGroup};
+use crate::{
+    CsvOptions, DatabaseAction, RunMode, SQLightError, SnapshotAction, SnapshotInfo, WorkerRequest,
+    WorkerResponse,
+    app::{
+        GlobalState, GlobalStateStoreFields,
+        button_menu_item::ButtonMenuItem,
+        config_element::{Either, Select},
+        default_filename,
+        header::prompt_name,
+        menu_aside::MenuAside,
+        menu_group::MenuGroup,
+        selectable_menu_item::SelectableMenuItem,
+    },
+    refresh_schema, request, send_request,
+};
+
+/// Apply an action to the databases of the current VFS, returns the databases afterwards.
+async fn manage(state: Store<GlobalState>, action: DatabaseAction) -> Option<Vec<String>> {
+    match request(state, WorkerRequest::Databases(action)).await {
+        Ok(WorkerResponse::Databases(Ok(databases))) => Some(databases),
+        Ok(WorkerResponse::Databases(Err(err))) | Err(err) => {
+            state.last_error().set(Some(SQLightError::new_worker(err)));
+            None
+        }
+        Ok(resp) => {
+            log::error!("Unexpected response to databases request: {resp:?}");
+            None
+        }
+    }
+}
+
+/// Apply an action to the snapshots of the opened database, returns the snapshots afterwards.
+async fn snapshots(state: Store<GlobalState>, action: SnapshotAction) -> Option<Vec<SnapshotInfo>> {
+    match request(state, WorkerRequest::Snapshots(action)).await {
+        Ok(WorkerResponse::Snapshots(Ok(snapshots))) => Some(snapshots),
+        Ok(WorkerResponse::Snapshots(Err(err))) | Err(err) => {
+            state.last_error().set(Some(SQLightError::new_worker(err)));
+            None
+        }
+        Ok(resp) => {
+            log::error!("Unexpected response to snapshots request: {resp:?}");
+            None
+        }
+    }
+}
+
+/// Choices of the csv delimiter.
+const DELIMITERS: [(char, &str); 4] = [
+    (',', "Comma"),
+    ('\t', "Tab"),
+    (';', "Semicolon"),
+    ('|', "Pipe"),
+];
+
+/// Choices of the csv quote, fields are not quoted if `None`.
+const QUOTES: [(Option<char>, &str); 3] = [
+    (Some('"'), "Double"),
+    (Some('\''), "Single"),
+    (None, "None"),
+];
 
 #[component]
-pub fn DatabaseMenu<L, D>(load: L, download: D) -> impl IntoView
+fn CsvOptionsMenu() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let defaults = CsvOptions::default();
+
+    let delimiter_change = move |event: Event| {
+        let value = event_target_value(&event);
+        if let Some((delimiter, _)) = DELIMITERS.into_iter().find(|(_, name)| *name == value) {
+            state.csv_options().write().delimiter = delimiter;
+        }
+    };
+
+    let quote_change = move |event: Event| {
+        let value = event_target_value(&event);
+        if let Some((quote, _)) = QUOTES.into_iter().find(|(_, name)| *name == value) {
+            state.csv_options().write().quote = quote;
+        }
+    };
+
+    view! {
+        <Select
+            name="Delimiter".into()
+            is_default=Box::new(move || state.csv_options().read().delimiter == defaults.delimiter)
+            on_change=delimiter_change
+        >
+            {move || {
+                DELIMITERS
+                    .into_iter()
+                    .map(|(delimiter, name)| {
+                        view! {
+                            <option
+                                value=name
+                                selected=delimiter == state.csv_options().read().delimiter
+                            >
+                                {name}
+                            </option>
+                        }
+                    })
+                    .collect_view()
+            }}
+        </Select>
+        <Either
+            id="csv_header".into()
+            name="Header Row".into()
+            a=true
+            b=false
+            a_label=Some("On".to_string())
+            b_label=Some("Off".to_string())
+            value=move || state.csv_options().read().header
+            is_default=Box::new(move || state.csv_options().read().header)
+            on_change=move |value: &bool| {
+                state.csv_options().write().header = *value;
+            }
+        />
+        <Select
+            name="Quote".into()
+            is_default=Box::new(move || state.csv_options().read().quote == defaults.quote)
+            on_change=quote_change
+        >
+            {move || {
+                QUOTES
+                    .into_iter()
+                    .map(|(quote, name)| {
+                        view! {
+                            <option value=name selected=quote == state.csv_options().read().quote>
+                                {name}
+                            </option>
+                        }
+                    })
+                    .collect_view()
+            }}
+        </Select>
+    }
+}
+
+#[component]
+pub fn DatabaseMenu<L, A, C, J, D, Z, S, O, W>(
+    load: L,
+    attach: A,
+    import_csv: C,
+    import_json: J,
+    download: D,
+    download_compressed: Z,
+    download_sql: S,
+    open_script: O,
+    save_script: W,
+) -> impl IntoView
 where
     L: Fn(MouseEvent) + Send + 'static,
+    A: Fn(MouseEvent) + Send + 'static,
+    C: Fn(MouseEvent) + Send + 'static,
+    J: Fn(MouseEvent) + Send + 'static,
     D: Fn(MouseEvent) + Send + 'static,
+    Z: Fn(MouseEvent) + Send + 'static,
+    S: Fn(MouseEvent) + Send + 'static,
+    O: Fn(MouseEvent) + Send + 'static,
+    W: Fn(MouseEvent) + Send + 'static,
 {
+    let state = expect_context::<Store<GlobalState>>();
+    let databases = RwSignal::new(Vec::<String>::new());
+    let snapshot_list = RwSignal::new(Vec::<SnapshotInfo>::new());
+
+    spawn_local(async move {
+        if let Some(names) = manage(state, DatabaseAction::List).await {
+            databases.set(names);
+        }
+        if let Some(infos) = snapshots(state, SnapshotAction::List).await {
+            snapshot_list.set(infos);
+        }
+    });
+
+    let on_new = move |_| {
+        let Some(name) = prompt_name("Name of the new database:", "") else {
+            return;
+        };
+        // the database is created once it is opened
+        databases.update(|databases| {
+            if !databases.contains(&name) {
+                databases.push(name.clone());
+            }
+        });
+        state.filename().set(name);
+    };
+
+    let on_rename = move |_| {
+        let from = state.filename().get_untracked();
+        let Some(to) = prompt_name("Rename the database to:", &from) else {
+            return;
+        };
+        spawn_local(async move {
+            if let Some(names) = manage(
+                state,
+                DatabaseAction::Rename {
+                    from,
+                    to: to.clone(),
+                },
+            )
+            .await
+            {
+                databases.set(names);
+                state.filename().set(to);
+            }
+        });
+    };
+
+    let on_duplicate = move |_| {
+        let from = state.filename().get_untracked();
+        let Some(to) = prompt_name("Name of the copy:", &format!("copy-{from}")) else {
+            return;
+        };
+        spawn_local(async move {
+            if let Some(names) = manage(state, DatabaseAction::Duplicate { from, to }).await {
+                databases.set(names);
+            }
+        });
+    };
+
+    let on_delete = move |_| {
+        let filename = state.filename().get_untracked();
+        if !window()
+            .confirm_with_message(&format!("Delete {filename}?"))
+            .unwrap_or_default()
+        {
+            return;
+        }
+        spawn_local(async move {
+            if let Some(names) = manage(state, DatabaseAction::Delete(filename)).await {
+                // reopen another database, or create the default one
+                let next = names.first().cloned().unwrap_or_else(default_filename);
+                databases.set(names);
+                state.filename().set(next);
+            }
+        });
+    };
+
+    let list = move || {
+        databases
+            .get()
+            .into_iter()
+            .map(|name| {
+                let selected = {
+                    let name = name.clone();
+                    move || *state.filename().read() == name
+                };
+                let on_click = {
+                    let name = name.clone();
+                    move |_| state.filename().set(name.clone())
+                };
+                view! {
+                    <SelectableMenuItem name=name selected=selected on_click=on_click>
+                        ""
+                    </SelectableMenuItem>
+                }
+            })
+            .collect_view()
+    };
+
+    let attachments = move || {
+        state
+            .attachments()
+            .get()
+            .into_iter()
+            .map(|attachment| {
+                let name = format!("Detach {}", attachment.alias);
+                let aside = format!("Detach {} from the connection.", attachment.filename);
+                let on_detach = move |_| {
+                    send_request(state, WorkerRequest::DetachDb(attachment.alias.clone()));
+                };
+                view! {
+                    <ButtonMenuItem name=name on_click=on_detach>
+                        <MenuAside>{aside}</MenuAside>
+                    </ButtonMenuItem>
+                }
+            })
+            .collect_view()
+    };
+
+    let revert = move |run: u32| {
+        if !window()
+            .confirm_with_message(&format!("Discard the changes since run #{run}?"))
+            .unwrap_or_default()
+        {
+            return;
+        }
+        spawn_local(async move {
+            if let Some(infos) = snapshots(state, SnapshotAction::Revert(run)).await {
+                snapshot_list.set(infos);
+                // the next run must not drop the reverted db
+                state.run_mode().update(|mode| {
+                    if *mode == RunMode::DropContext {
+                        *mode = RunMode::KeepContext;
+                    }
+                });
+                refresh_schema(state);
+            }
+        });
+    };
+
+    let snapshot_items = move || {
+        snapshot_list
+            .get()
+            .into_iter()
+            .rev()
+            .map(|snapshot| {
+                let name = format!("Revert to before run #{}", snapshot.run);
+                let sql = snapshot.sql.trim();
+                let preview = match sql.char_indices().nth(60) {
+                    Some((idx, _)) => format!("{}…", &sql[..idx]),
+                    None => sql.to_string(),
+                };
+                let aside = format!("{preview} ({} KiB)", snapshot.size.div_ceil(1024));
+                view! {
+                    <ButtonMenuItem name=name on_click=move |_| revert(snapshot.run)>
+                        <MenuAside>{aside}</MenuAside>
+                    </ButtonMenuItem>
+                }
+            })
+            .collect_view()
+    };
+
     view! {
         <MenuGroup title="Database".into()>
             <ButtonMenuItem name="Load".into() on_click=load>
-                <MenuAside>"The KEEP CONTEXT will be automatically enabled."</MenuAside>
+                <MenuAside>
+                    "The KEEP CONTEXT will be automatically enabled. Also accepts .gz, .zst and single-file .zip."
+                </MenuAside>
             </ButtonMenuItem>
             <ButtonMenuItem name="Download".into() on_click=download>
-                <MenuAside>"Will be downloaded as test.db."</MenuAside>
+                <MenuAside>
+                    {move || format!("Will be downloaded as {}.", state.filename().read())}
+                </MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Download Compressed".into() on_click=download_compressed>
+                <MenuAside>
+                    {move || format!("Will be downloaded as {}.gz.", state.filename().read())}
+                </MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Download as SQL".into() on_click=download_sql>
+                <MenuAside>"The schema and rows as sql statements, like the .dump of the shell."</MenuAside>
+            </ButtonMenuItem>
+        </MenuGroup>
+        <MenuGroup title="Script".into()>
+            <ButtonMenuItem name="Open Script".into() on_click=open_script>
+                <MenuAside>"Open a .sql file in the editor, replacing its code."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Save Script".into() on_click=save_script>
+                <MenuAside>
+                    {move || match &*state.script_name().read() {
+                        Some(name) => format!("Will be downloaded as {name}."),
+                        None => "Download the code in the editor as a .sql file.".to_string(),
+                    }}
+                </MenuAside>
+            </ButtonMenuItem>
+        </MenuGroup>
+        <MenuGroup title="Attached".into()>
+            <ButtonMenuItem name="Attach".into() on_click=attach>
+                <MenuAside>"Load a database next to main, under an alias."</MenuAside>
+            </ButtonMenuItem>
+            {attachments}
+        </MenuGroup>
+        <MenuGroup title="Import".into()>
+            <ButtonMenuItem name="Import CSV".into() on_click=import_csv>
+                <MenuAside>
+                    "Import the rows of a csv or tsv file into a new or existing table."
+                </MenuAside>
+            </ButtonMenuItem>
+            <CsvOptionsMenu />
+            <ButtonMenuItem name="Import JSON".into() on_click=import_json>
+                <MenuAside>
+                    "Import a json array of objects or ndjson, nested values are kept as json text."
+                </MenuAside>
+            </ButtonMenuItem>
+        </MenuGroup>
+        <Show when=move || !snapshot_list.read().is_empty() fallback=|| ()>
+            <MenuGroup title="Snapshots".into()>{snapshot_items}</MenuGroup>
+        </Show>
+        <MenuGroup title="Databases of the VFS".into()>
+            {list}
+            <ButtonMenuItem name="New".into() on_click=on_new>
+                <MenuAside>"Create and switch to a new database."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Rename".into() on_click=on_rename>
+                <MenuAside>"Rename the current database."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Duplicate".into() on_click=on_duplicate>
+                <MenuAside>"Copy the current database under a new name."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Delete".into() on_click=on_delete>
+                <MenuAside>"Delete the current database."</MenuAside>
             </ButtonMenuItem>
         </MenuGroup>
     }
@@ -3,11 +3,12 @@ use istyles::istyles;
 use leptos::prelude::*;
 use reactive_stores::Store;
 use wasm_bindgen::{JsCast, prelude::Closure};
-use web_sys::UrlSearchParams;
+use web_sys::DragEvent;
 
 use crate::{
-    SQLightError,
-    app::{GlobalState, GlobalStateStoreFields, header::execute},
+    FragileComfirmed, LoadDbOptions, SQLightError, WorkerRequest,
+    app::{GlobalState, GlobalStateStoreFields, header::execute, shared::SharedPayload},
+    send_request,
 };
 
 istyles!(styles, "assets/module.postcss/editor.module.css.map");
@@ -19,11 +20,29 @@ pub fn Editor() -> impl IntoView {
     let editor_ref = NodeRef::new();
 
     editor_ref.on_load(move |_| {
-        let shared_code = || {
-            let search = window().location().search().ok()?;
-            let params = UrlSearchParams::new_with_str(&search).ok()?;
-            params.get("code")
-        };
+        let shared_code = SharedPayload::from_location().map(|shared| {
+            if !shared.parameters.is_empty() {
+                state.parameters().set(shared.parameters);
+            }
+            // queued before any run of the shared code
+            if let Some(db) = shared.db
+                && window()
+                    .confirm_with_message(&format!(
+                        "The link contains a database, load it in place of {}?",
+                        state.filename().read_untracked()
+                    ))
+                    .unwrap_or_default()
+            {
+                send_request(
+                    state,
+                    WorkerRequest::LoadDb(LoadDbOptions {
+                        data: js_sys::Uint8Array::from(db.as_slice()),
+                        encryption: None,
+                    }),
+                );
+            }
+            shared.code
+        });
 
         let opt = EditorOptionsBuilder::default()
             .mode("ace/mode/sql")
@@ -38,7 +57,7 @@ pub fn Editor() -> impl IntoView {
                 }
                 .as_deref(),
             )
-            .value(&shared_code().unwrap_or_else(|| state.sql().get_untracked()))
+            .value(&shared_code.unwrap_or_else(|| state.sql().get_untracked()))
             .build();
 
         match aceditor::Editor::open("ace_editor", Some(&opt)) {
@@ -68,9 +87,31 @@ pub fn Editor() -> impl IntoView {
                 .set(Some(SQLightError::new_ace_editor(err)));
         }
     });
+    // routed by the database button, like a selected file
+    let on_drop = move |ev: DragEvent| {
+        let file = ev
+            .data_transfer()
+            .and_then(|transfer| transfer.files())
+            .and_then(|files| files.get(0));
+        if let Some(file) = file {
+            ev.prevent_default();
+            state.dropped_file().set(Some(FragileComfirmed::new(file)));
+        }
+    };
+
     view! {
-        <div class=styles::container>
+        <div
+            class=styles::container
+            on:dragover=|ev: DragEvent| ev.prevent_default()
+            on:drop=on_drop
+        >
             <div node_ref=editor_ref id="ace_editor" class=styles::ace></div>
+            {move || {
+                state
+                    .script_name()
+                    .get()
+                    .map(|name| view! { <div class=styles::title>{name}</div> })
+            }}
         </div>
     }
 }
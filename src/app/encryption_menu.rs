@@ -0,0 +1,181 @@
+use istyles::istyles;
+use leptos::prelude::*;
+use reactive_stores::Store;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    CIPHERS, Encryption, EncryptionAction, EncryptionStatus, SQLightError, WorkerRequest,
+    WorkerResponse,
+    app::{
+        GlobalState, GlobalStateStoreFields, button_menu_item::ButtonMenuItem,
+        config_element::ConfigElement, menu_aside::MenuAside, menu_group::MenuGroup,
+    },
+    request,
+};
+
+istyles!(
+    styles,
+    "assets/module.postcss/encryption_menu.module.css.map"
+);
+
+/// Apply an action to the encryption of the main database, returns its status afterwards.
+async fn encryption(
+    state: Store<GlobalState>,
+    action: EncryptionAction,
+) -> Option<EncryptionStatus> {
+    match request(state, WorkerRequest::Encryption(action)).await {
+        Ok(WorkerResponse::Encryption(Ok(status))) => Some(status),
+        Ok(WorkerResponse::Encryption(Err(err))) | Err(err) => {
+            state.last_error().set(Some(SQLightError::new_worker(err)));
+            None
+        }
+        Ok(resp) => {
+            log::error!("Unexpected response to encryption request: {resp:?}");
+            None
+        }
+    }
+}
+
+#[component]
+pub fn EncryptionMenu() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+
+    let current = state.encryption().get_untracked();
+    let cipher = RwSignal::new(
+        current
+            .as_ref()
+            .map_or_else(|| CIPHERS[0].to_string(), |e| e.cipher.clone()),
+    );
+    let legacy = RwSignal::new(current.as_ref().map_or(0, |e| e.legacy));
+    let key = RwSignal::new(current.map(|e| e.key).unwrap_or_default());
+    let status = RwSignal::new(None::<EncryptionStatus>);
+
+    // the status of the db as reopened, e.g. with the applied key
+    Effect::new(move || {
+        state.opens().track();
+        spawn_local(async move {
+            status.set(encryption(state, EncryptionAction::Status).await);
+        });
+    });
+
+    let form = move || {
+        Some(Encryption {
+            cipher: cipher.get_untracked(),
+            legacy: legacy.get_untracked(),
+            key: key.get_untracked(),
+        })
+        .filter(|encryption| !encryption.key.is_empty())
+    };
+
+    // the db is reopened with the key, the status follows the reopen
+    let on_apply = move |_| {
+        state.encryption().set(form());
+    };
+
+    let rekey = move |encryption: Option<Encryption>| {
+        spawn_local(async move {
+            let action = EncryptionAction::Rekey(encryption.clone());
+            if let Some(new_status) = self::encryption(state, action).await {
+                // already applied to the opened db, no need to reopen it
+                *state.encryption().write_untracked() = encryption;
+                status.set(Some(new_status));
+            }
+        });
+    };
+
+    let on_rekey = move |_| {
+        if let Some(encryption) = form() {
+            rekey(Some(encryption));
+        }
+    };
+
+    let on_remove = move |_| {
+        if window()
+            .confirm_with_message("Store the database without encryption?")
+            .unwrap_or_default()
+        {
+            key.set(String::new());
+            rekey(None);
+        }
+    };
+
+    let status_text = move || match &*status.read() {
+        Some(EncryptionStatus {
+            cipher,
+            encrypted: true,
+        }) => format!("Encrypted with {cipher}."),
+        Some(EncryptionStatus {
+            encrypted: false, ..
+        }) => "Not encrypted.".to_string(),
+        None => "Unknown.".to_string(),
+    };
+
+    let ciphers = CIPHERS
+        .into_iter()
+        .map(|option| {
+            view! {
+                <option value=option selected=move || *cipher.read() == option>
+                    {option}
+                </option>
+            }
+        })
+        .collect_view();
+
+    let legacies = (0..=4u32)
+        .map(|option| {
+            let label = if option == 0 {
+                "Current".to_string()
+            } else {
+                option.to_string()
+            };
+            view! {
+                <option value=option.to_string() selected=move || *legacy.read() == option>
+                    {label}
+                </option>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <MenuGroup title="Encryption".into()>
+            <ConfigElement name="Cipher".into()>
+                <select class=styles::input on:change=move |ev| cipher.set(event_target_value(&ev))>
+                    {ciphers}
+                </select>
+            </ConfigElement>
+            <ConfigElement name="Legacy".into()>
+                <select
+                    class=styles::input
+                    on:change=move |ev| {
+                        legacy.set(event_target_value(&ev).parse().unwrap_or_default())
+                    }
+                >
+                    {legacies}
+                </select>
+            </ConfigElement>
+            <ConfigElement
+                name="Key".into()
+                aside=Some(
+                    view! { <MenuAside>"Only kept in memory, it is never saved."</MenuAside> }
+                        .into_any(),
+                )
+            >
+                <input
+                    class=styles::input
+                    type="password"
+                    prop:value=move || key.get()
+                    on:input=move |ev| key.set(event_target_value(&ev))
+                />
+            </ConfigElement>
+            <ButtonMenuItem name="Apply Key".into() on_click=on_apply>
+                <MenuAside>"Reopen the database with this key."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Rekey".into() on_click=on_rekey>
+                <MenuAside>"Encrypt the database with this cipher and key."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Remove Encryption".into() on_click=on_remove>
+                <MenuAside>{status_text}</MenuAside>
+            </ButtonMenuItem>
+        </MenuGroup>
+    }
+}
@@ -1,14 +1,18 @@
+use aceditor::{Point, Range};
 use istyles::istyles;
 use leptos::{html::Input, prelude::*, tachys::html};
 use reactive_stores::Store;
 use sqlformat::{FormatOptions, QueryParams};
 use wasm_bindgen::{JsCast, prelude::Closure};
-use web_sys::{Blob, Event, FileReader, HtmlInputElement, MouseEvent, Url, UrlSearchParams};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{Blob, Event, FileReader, HtmlInputElement, MouseEvent, Url};
 
 use crate::{
-    FragileComfirmed, LoadDbOptions, RunOptions, SQLightError, WorkerRequest,
+    AttachDbOptions, CIPHERS, Compressed, Encryption, ExplainOptions, FragileComfirmed,
+    ImportCsvOptions, ImportJsonOptions, LoadDbOptions, RunMode, RunOptions, RunTarget,
+    SQLightError, WorkerRequest, WorkerResponse,
     app::{
-        ImportProgress,
+        Exported, ImportProgress,
         advanced_options_menu::AdvancedOptionsMenu,
         button_set::{Button, ButtonSet, IconButton, LinkButton, Rule},
         config_menu::ConfigMenu,
@@ -17,11 +21,12 @@ use crate::{
         icon::{build_icon, config_icon, expandable_icon, github_icon, more_options_icon},
         output::change_focus,
         pop_button::PopButton,
-        state::{Focus, GlobalState, GlobalStateStoreFields},
+        shared::SharedPayload,
+        state::{Focus, GlobalState, GlobalStateStoreFields, PendingRun},
         tools_menu::ToolsMenu,
         vfs_menu::VfsMenu,
     },
-    send_request,
+    interrupt_run, request, send_request,
 };
 
 istyles!(styles, "assets/module.postcss/header.module.css.map");
@@ -47,6 +52,10 @@ pub fn Header() -> impl IntoView {
                         <Rule />
                         <AdvancedOptionsMenuButton menu_container=menu_container />
                     </ButtonSet>
+
+                    <ButtonSet>
+                        <SchemaButton />
+                    </ButtonSet>
                 </div>
                 <div class=styles::right>
                     <input type="file" node_ref=input_ref style="display: none" />
@@ -86,27 +95,128 @@ pub fn execute(state: Store<GlobalState>) -> Box<dyn Fn() + Send + 'static> {
         drop(editor_guard);
 
         state.sql().set(code.clone());
-        change_focus(state, Some(Focus::Execute));
-        std::mem::take(&mut *state.output().write());
 
         let run_selected_code =
             !selected_code.is_empty() && state.run_selected_sql().get_untracked();
 
-        send_request(
+        run(
             state,
-            WorkerRequest::Run(RunOptions {
-                embed: false,
+            RunOptions {
+                target: RunTarget::Output,
                 sql: if run_selected_code {
                     selected_code
                 } else {
                     code
                 },
-                clear_on_prepare: !*state.keep_ctx().read_untracked(),
-            }),
+                mode: state.run_mode().get_untracked(),
+                continue_on_error: state.continue_on_error().get_untracked(),
+                parameters: vec![],
+                asked: vec![],
+                snapshot_limit: 0,
+            },
         );
     })
 }
 
+/// Run the sql, the parameter form is shown first if it has any parameters.
+fn run(state: Store<GlobalState>, options: RunOptions) {
+    if let Some(PendingRun { options: None, .. }) = state.pending_run().write().take() {
+        // a paused run would block the new one
+        send_request(state, WorkerRequest::ParameterValues(None));
+    }
+    spawn_local(async move {
+        match request(state, WorkerRequest::Parameters(options.sql.clone())).await {
+            Ok(WorkerResponse::Parameters(Ok(names))) if !names.is_empty() => {
+                state.pending_run().set(Some(PendingRun {
+                    options: Some(options),
+                    names,
+                }));
+            }
+            // the run reports the error if the sql can not be prepared
+            _ => start_run(state, options),
+        }
+    });
+}
+
+/// Run the sql with the values of the parameter form.
+pub fn start_run(state: Store<GlobalState>, mut options: RunOptions) {
+    if options.target == RunTarget::Output {
+        change_focus(state, Some(Focus::Execute));
+        std::mem::take(&mut *state.output().write());
+    }
+    state.running().set(true);
+    options.parameters = state.parameters().get_untracked();
+    options.snapshot_limit = state.snapshot_limit().get_untracked() as usize * 1024 * 1024;
+    send_request(state, WorkerRequest::Run(options));
+}
+
+fn explain_query_plan(state: Store<GlobalState>, at_cursor: bool) {
+    let editor_guard = state.editor().read_untracked();
+    let Some(editor) = editor_guard.as_ref() else {
+        return;
+    };
+
+    let (code, selected_code) = (editor.get_value(), editor.get_selected_value());
+    // byte offset of the cursor, as used by statement positions
+    let cursor = at_cursor.then(|| {
+        let start = Point { row: 0, column: 0 };
+        let end = editor.get_range().end;
+        editor.get_text_range(Range { start, end }).len()
+    });
+
+    drop(editor_guard);
+
+    let explain_selected_code =
+        !at_cursor && !selected_code.is_empty() && state.run_selected_sql().get_untracked();
+
+    send_request(
+        state,
+        WorkerRequest::QueryPlan(ExplainOptions {
+            sql: if explain_selected_code {
+                selected_code
+            } else {
+                code
+            },
+            cursor,
+        }),
+    );
+}
+
+fn explain_bytecode(state: Store<GlobalState>) {
+    let editor_guard = state.editor().read_untracked();
+    let Some(editor) = editor_guard.as_ref() else {
+        return;
+    };
+
+    let (code, selected_code) = (editor.get_value(), editor.get_selected_value());
+
+    drop(editor_guard);
+
+    change_focus(state, Some(Focus::Bytecode));
+    state.running().set(true);
+
+    let explain_selected_code =
+        !selected_code.is_empty() && state.run_selected_sql().get_untracked();
+
+    // nothing is executed, so the context is kept and every statement is explained
+    send_request(
+        state,
+        WorkerRequest::Run(RunOptions {
+            target: RunTarget::Bytecode,
+            sql: if explain_selected_code {
+                selected_code
+            } else {
+                code
+            },
+            mode: RunMode::KeepContext,
+            continue_on_error: true,
+            parameters: vec![],
+            asked: vec![],
+            snapshot_limit: 0,
+        }),
+    );
+}
+
 #[component]
 fn ExecuteButton() -> impl IntoView {
     let state = expect_context::<Store<GlobalState>>();
@@ -117,6 +227,23 @@ fn ExecuteButton() -> impl IntoView {
         <Button is_primary=true icon_right=build_icon() on_click=move |_| on_click()>
             "Run"
         </Button>
+        <Show when=move || *state.running().read() fallback=|| ()>
+            <Rule />
+            <Button on_click=move |_| interrupt_run(state)>"Stop"</Button>
+        </Show>
+    }
+}
+
+#[component]
+fn SchemaButton() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+
+    view! {
+        <Button on_click=move |_| {
+            state.show_schema().update(|show| *show = !*show)
+        }>
+            {move || if *state.show_schema().read() { "Hide Schema" } else { "Schema" }}
+        </Button>
     }
 }
 
@@ -149,7 +276,7 @@ fn ContextMenuButton(menu_container: NodeRef<html::element::Div>) -> impl IntoVi
     let button = move |toggle, node_ref| {
         view! {
             <Button icon_right=expandable_icon() on_click=toggle node_ref=node_ref>
-                {move || if *state.keep_ctx().read() { "Keep Context" } else { "Drop Context" }}
+                {move || state.run_mode().read().value()}
             </Button>
         }
         .into_any()
@@ -251,13 +378,17 @@ fn ToolsButton(menu_container: NodeRef<html::element::Div>) -> impl IntoView {
         let sql = editor.get_value();
         drop(editor_guard);
 
-        send_request(
+        run(
             state,
-            WorkerRequest::Run(RunOptions {
-                embed: true,
+            RunOptions {
+                target: RunTarget::Embed,
                 sql,
-                clear_on_prepare: !*state.keep_ctx().read_untracked(),
-            }),
+                mode: state.run_mode().get_untracked(),
+                continue_on_error: false,
+                parameters: vec![],
+                asked: vec![],
+                snapshot_limit: 0,
+            },
         );
 
         signal.set(false);
@@ -275,6 +406,18 @@ fn ToolsButton(menu_container: NodeRef<html::element::Div>) -> impl IntoView {
                         on_embed=move |e| {
                             on_embed(e, signal);
                         }
+                        on_plan=move |_| {
+                            explain_query_plan(state, false);
+                            signal.set(false);
+                        }
+                        on_plan_at_cursor=move |_| {
+                            explain_query_plan(state, true);
+                            signal.set(false);
+                        }
+                        on_bytecode=move |_| {
+                            explain_bytecode(state);
+                            signal.set(false);
+                        }
                         on_internal=move |_| {
                             signal.set(false);
                         }
@@ -287,6 +430,114 @@ fn ToolsButton(menu_container: NodeRef<html::element::Div>) -> impl IntoView {
     }
 }
 
+/// What the next selected file is imported as.
+#[derive(Clone, PartialEq)]
+enum ImportKind {
+    /// Replace the main db.
+    Load,
+    /// Attach the db under this alias.
+    Attach(String),
+    /// Import the rows into a table of the main db.
+    Csv,
+    /// Import the objects into a table of the main db.
+    Json,
+    /// Open the script in the editor.
+    Script,
+    /// Dropped on the editor, a script if it is text, otherwise a db to load.
+    Dropped,
+}
+
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+fn has_sqlite_header(data: &js_sys::Uint8Array) -> bool {
+    if data.length() < 16 {
+        return false;
+    }
+    let mut header = [0; 16];
+    data.subarray(0, 16).copy_to(&mut header);
+    &header == SQLITE_HEADER
+}
+
+/// Bytes of a dropped file checked to tell a script from a database.
+const SNIFF_LEN: u32 = 4096;
+
+/// Whether the start of the data reads as text, without copying the whole file.
+fn looks_like_text(data: &js_sys::Uint8Array) -> bool {
+    let prefix = data.subarray(0, data.length().min(SNIFF_LEN)).to_vec();
+    if prefix.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(&prefix) {
+        Ok(_) => true,
+        // a character may be cut at the end of the prefix
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+/// Replace the code in the editor, the filename becomes the title of the buffer.
+fn open_script(state: Store<GlobalState>, filename: String, data: &js_sys::Uint8Array) {
+    let code = String::from_utf8_lossy(&data.to_vec()).into_owned();
+    if let Some(editor) = &*state.editor().read_untracked() {
+        editor.set_value(code.clone());
+    }
+    state.sql().set(code);
+    state.script_name().set(Some(filename));
+}
+
+pub fn prompt_name(message: &str, default: &str) -> Option<String> {
+    window()
+        .prompt_with_message_and_default(message, default)
+        .ok()
+        .flatten()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Ask for the key of a db without a plain SQLite header.
+///
+/// Returns `None` if cancelled, `Some(None)` if the db is not encrypted.
+fn prompt_encryption(
+    state: Store<GlobalState>,
+    data: &js_sys::Uint8Array,
+) -> Option<Option<Encryption>> {
+    if !state.multiple_ciphers().get_untracked() || data.length() < 16 || has_sqlite_header(data) {
+        return Some(None);
+    }
+
+    let mut magic = [0; 4];
+    data.subarray(0, 4).copy_to(&mut magic);
+    // decompressed by the worker, the header is unknown until then
+    let compressed = Compressed::detect(&magic).is_some();
+    let message = if compressed {
+        "If the compressed database is encrypted, enter its key:"
+    } else {
+        "The database is encrypted, enter its key:"
+    };
+
+    let window = window();
+    let key = window.prompt_with_message(message).ok().flatten()?;
+    if compressed && key.is_empty() {
+        return Some(None);
+    }
+    if key.is_empty() {
+        window.alert_with_message("The key must not be empty.").ok();
+        return None;
+    }
+    let current = state.encryption().get_untracked();
+    let cipher = window
+        .prompt_with_message_and_default(
+            &format!("Cipher ({}):", CIPHERS.join(", ")),
+            current.as_ref().map_or(CIPHERS[0], |e| e.cipher.as_str()),
+        )
+        .ok()
+        .flatten()?;
+    Some(Some(Encryption {
+        cipher: cipher.trim().to_string(),
+        legacy: current.map_or(0, |e| e.legacy),
+        key,
+    }))
+}
+
 #[component]
 fn DatabaseButton(
     input_ref: NodeRef<Input>,
@@ -304,20 +555,34 @@ fn DatabaseButton(
     };
 
     let (file, set_file) = signal::<Option<FragileComfirmed<web_sys::File>>>(None);
+    let (import_kind, set_import_kind) = signal(ImportKind::Load);
 
     Effect::new(move || {
         if let Some(file) = &*file.read() {
             let filename = file.name();
+            // the table of a csv file is named after it by default
+            let stem = filename
+                .rsplit_once('.')
+                .map_or(filename.as_str(), |(stem, _)| stem)
+                .to_string();
+            // opening a script is not an import, a dropped file is only known once read
+            let show_progress = !matches!(
+                import_kind.get_untracked(),
+                ImportKind::Script | ImportKind::Dropped
+            );
+            let name = filename.clone();
 
             if let Ok(reader) = FileReader::new() {
                 let on_progress = FragileComfirmed::new(Closure::wrap(Box::new(
                     move |ev: web_sys::ProgressEvent| {
-                        if ev.length_computable() {
+                        if show_progress && ev.length_computable() {
                             state.import_progress().set(Some(ImportProgress {
                                 filename: filename.clone(),
                                 loaded: ev.loaded(),
                                 total: ev.total(),
                                 opened: None,
+                                processed: None,
+                                imported: None,
                             }));
                         }
                     },
@@ -343,7 +608,62 @@ fn DatabaseButton(
                         let result = reader.result().unwrap();
                         let array_buffer = result.unchecked_into::<js_sys::ArrayBuffer>();
                         let data = js_sys::Uint8Array::new(&array_buffer);
-                        send_request(state, WorkerRequest::LoadDb(LoadDbOptions { data }));
+                        let kind = match import_kind.get_untracked() {
+                            ImportKind::Dropped
+                                if !has_sqlite_header(&data) && looks_like_text(&data) =>
+                            {
+                                ImportKind::Script
+                            }
+                            kind => kind,
+                        };
+                        if kind == ImportKind::Dropped {
+                            let total = f64::from(data.length());
+                            state.import_progress().set(Some(ImportProgress {
+                                filename: name.clone(),
+                                loaded: total,
+                                total,
+                                opened: None,
+                                processed: None,
+                                imported: None,
+                            }));
+                        }
+                        let req = match kind {
+                            ImportKind::Script => {
+                                open_script(state, name.clone(), &data);
+                                return;
+                            }
+                            ImportKind::Attach(alias) => {
+                                WorkerRequest::AttachDb(AttachDbOptions { alias, data })
+                            }
+                            ImportKind::Csv => {
+                                let Some(table) = prompt_name("Import the rows into table:", &stem)
+                                else {
+                                    state.import_progress().set(None);
+                                    return;
+                                };
+                                WorkerRequest::ImportCsv(ImportCsvOptions {
+                                    data,
+                                    table,
+                                    options: state.csv_options().get_untracked(),
+                                })
+                            }
+                            ImportKind::Json => {
+                                let Some(table) = prompt_name("Import the rows into table:", &stem)
+                                else {
+                                    state.import_progress().set(None);
+                                    return;
+                                };
+                                WorkerRequest::ImportJson(ImportJsonOptions { data, table })
+                            }
+                            ImportKind::Load | ImportKind::Dropped => {
+                                let Some(encryption) = prompt_encryption(state, &data) else {
+                                    state.import_progress().set(None);
+                                    return;
+                                };
+                                WorkerRequest::LoadDb(LoadDbOptions { data, encryption })
+                            }
+                        };
+                        send_request(state, req);
                     })
                         as Box<dyn FnMut(_)>));
 
@@ -375,7 +695,7 @@ fn DatabaseButton(
         }
     };
 
-    let on_load = move |_: MouseEvent, signal: WriteSignal<bool>| {
+    let select_file = move || {
         if let Some(input) = &*input_ref.read() {
             if input.onchange().is_none() {
                 let callback = Closure::wrap(Box::new(on_change) as Box<dyn Fn(Event)>);
@@ -385,9 +705,63 @@ fn DatabaseButton(
             input.set_value("");
             input.click();
         }
+    };
+
+    let on_load = move |_: MouseEvent, signal: WriteSignal<bool>| {
+        set_import_kind.set(ImportKind::Load);
+        select_file();
         signal.set(false);
     };
 
+    let on_attach = move |_: MouseEvent, signal: WriteSignal<bool>| {
+        if let Some(alias) = prompt_name("Attach the database as:", "") {
+            set_import_kind.set(ImportKind::Attach(alias));
+            select_file();
+        }
+        signal.set(false);
+    };
+
+    let on_import = move |kind: ImportKind, signal: WriteSignal<bool>| {
+        set_import_kind.set(kind);
+        select_file();
+        signal.set(false);
+    };
+
+    // files dropped on the editor
+    Effect::new(move || {
+        state.dropped_file().track();
+
+        if let Some(dropped) = state.dropped_file().write_untracked().take() {
+            set_import_kind.set(ImportKind::Dropped);
+            set_file.set(Some(dropped));
+        }
+    });
+
+    let on_save_script = move |_: MouseEvent, signal: WriteSignal<bool>| {
+        signal.set(false);
+        let Some(code) = state
+            .editor()
+            .read_untracked()
+            .as_ref()
+            .map(|e| e.get_value())
+        else {
+            return;
+        };
+        let title = state.script_name().get_untracked();
+        let Some(filename) = prompt_name(
+            "Save the script as:",
+            title.as_deref().unwrap_or("script.sql"),
+        ) else {
+            return;
+        };
+        state.sql().set(code.clone());
+        state.script_name().set(Some(filename.clone()));
+        state.exported().set(Some(Exported {
+            filename,
+            data: FragileComfirmed::new(js_sys::Uint8Array::from(code.as_bytes())),
+        }));
+    };
+
     Effect::new(move || {
         state.exported().track();
 
@@ -416,8 +790,8 @@ fn DatabaseButton(
         Url::revoke_object_url(&url).unwrap();
     });
 
-    let on_download = move |_: MouseEvent, signal: WriteSignal<bool>| {
-        send_request(state, WorkerRequest::DownloadDb);
+    let on_download = move |req: WorkerRequest, signal: WriteSignal<bool>| {
+        send_request(state, req);
         signal.set(false);
     };
 
@@ -428,7 +802,18 @@ fn DatabaseButton(
                 view! {
                     <DatabaseMenu
                         load=move |e| on_load(e, signal)
-                        download=move |e| on_download(e, signal)
+                        attach=move |e| on_attach(e, signal)
+                        import_csv=move |_| on_import(ImportKind::Csv, signal)
+                        import_json=move |_| on_import(ImportKind::Json, signal)
+                        open_script=move |_| on_import(ImportKind::Script, signal)
+                        save_script=move |e| on_save_script(e, signal)
+                        download=move |_| {
+                            on_download(WorkerRequest::DownloadDb { compressed: false }, signal)
+                        }
+                        download_compressed=move |_| {
+                            on_download(WorkerRequest::DownloadDb { compressed: true }, signal)
+                        }
+                        download_sql=move |_| on_download(WorkerRequest::DumpDb, signal)
                     />
                 }
                     .into_any()
@@ -452,14 +837,9 @@ fn ShareButton() -> impl IntoView {
             return;
         };
 
-        if let Ok(href) = window().location().href().and_then(|href| {
-            let url = Url::new(&href)?;
-            let params = UrlSearchParams::new()?;
-            params.set("code", &code);
-            url.set_search(&params.to_string().as_string().unwrap());
-            Ok(url.href())
-        }) {
-            state.share_href().set(Some(href));
+        match SharedPayload::new(code, state.parameters().get_untracked()).href() {
+            Ok(href) => state.share_href().set(Some(href)),
+            Err(err) => log::error!("Failed to create the share link: {err}"),
         }
 
         change_focus(state, Some(Focus::Share));
@@ -6,6 +6,7 @@ mod config_menu;
 mod context_menu;
 mod database_menu;
 mod editor;
+mod encryption_menu;
 mod header;
 mod icon;
 mod loader;
@@ -13,10 +14,13 @@ mod menu_aside;
 mod menu_group;
 mod menu_item;
 mod output;
+mod parameters;
 mod playground;
 mod pop_button;
+mod schema;
 mod select_one;
 mod selectable_menu_item;
+mod shared;
 mod state;
 mod tools_menu;
 mod vfs_menu;
@@ -1,14 +1,19 @@
+mod bytecode;
 mod execute;
+mod export;
 mod header;
 mod loader;
+mod plan;
 mod section;
 mod share;
 mod simple_pane;
 mod status;
 
+use bytecode::Bytecode;
 use execute::Execute;
 use istyles::istyles;
 use leptos::prelude::*;
+use plan::Plan;
 use reactive_stores::Store;
 use share::Share;
 use status::Status;
@@ -46,6 +51,20 @@ fn body() -> AnyView {
                         <Execute />
                     </Show>
 
+                    <Show
+                        when=move || matches!(*state.focus().read(), Some(Focus::Plan))
+                        fallback=|| ()
+                    >
+                        <Plan />
+                    </Show>
+
+                    <Show
+                        when=move || matches!(*state.focus().read(), Some(Focus::Bytecode))
+                        fallback=|| ()
+                    >
+                        <Bytecode />
+                    </Show>
+
                     <Show
                         when=move || matches!(*state.focus().read(), Some(Focus::Share))
                         fallback=|| ()
@@ -101,6 +120,18 @@ pub fn Output() -> impl IntoView {
                         on:click=move |_| change_focus(state, Some(Focus::Execute))
                     />
 
+                    <Tab
+                        kind=Focus::Plan
+                        label="Query Plan".into()
+                        on:click=move |_| change_focus(state, Some(Focus::Plan))
+                    />
+
+                    <Tab
+                        kind=Focus::Bytecode
+                        label="Bytecode".into()
+                        on:click=move |_| change_focus(state, Some(Focus::Bytecode))
+                    />
+
                     <Tab
                         kind=Focus::Share
                         label="Share".into()
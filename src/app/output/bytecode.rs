@@ -0,0 +1,397 @@
+use istyles::istyles;
+use leptos::prelude::*;
+use reactive_stores::Store;
+
+use crate::app::{
+    output::{header::Header, loader::Loader, section::Section, simple_pane::SimplePane},
+    state::{GlobalState, GlobalStateStoreFields},
+};
+use crate::{SQLiteStatementResult, SQLiteStatementValues, SQLiteValue};
+
+istyles!(
+    styles,
+    "assets/module.postcss/output/bytecode.module.css.map"
+);
+
+/// Opcodes that may jump to the address in P2.
+const JUMP_OPCODES: &[&str] = &[
+    "Goto",
+    "Gosub",
+    "InitCoroutine",
+    "Yield",
+    "Init",
+    "Once",
+    "If",
+    "IfNot",
+    "IfPos",
+    "IfNotZero",
+    "IfNullRow",
+    "IfNoHope",
+    "IfNotOpen",
+    "IfEmpty",
+    "IfSizeBetween",
+    "IsNull",
+    "NotNull",
+    "IsType",
+    "Eq",
+    "Ne",
+    "Lt",
+    "Le",
+    "Gt",
+    "Ge",
+    "ElseEq",
+    "Jump",
+    "MustBeInt",
+    "DecrJumpZero",
+    "Rewind",
+    "Last",
+    "Sort",
+    "SorterSort",
+    "Next",
+    "Prev",
+    "SorterNext",
+    "VNext",
+    "VFilter",
+    "SeekLT",
+    "SeekLE",
+    "SeekGE",
+    "SeekGT",
+    "SeekRowid",
+    "NotExists",
+    "NotFound",
+    "Found",
+    "NoConflict",
+    "IdxLT",
+    "IdxLE",
+    "IdxGT",
+    "IdxGE",
+    "RowSetRead",
+    "RowSetTest",
+    "Filter",
+];
+
+/// Opcodes that close a loop by jumping back to its start, as in the `.explain` mode of the shell.
+const NEXT_OPCODES: &[&str] = &[
+    "Next",
+    "Prev",
+    "VPrev",
+    "VNext",
+    "SorterNext",
+    "NextIfOpen",
+    "PrevIfOpen",
+    "RowSetTest",
+];
+
+/// Opcodes at the start of a loop that is closed by a `Goto`.
+const YIELD_OPCODES: &[&str] = &["Yield", "SeekLT", "SeekGT", "RowSetRead", "Rewind"];
+
+/// What P1 to P5 of common opcodes mean.
+fn operands(opcode: &str) -> [&'static str; 5] {
+    match opcode {
+        "OpenRead" | "OpenWrite" => ["cursor", "root page", "database", "columns or index", ""],
+        "OpenEphemeral" | "OpenAutoindex" => ["cursor", "columns", "", "key info", ""],
+        "Column" => ["cursor", "column", "into register", "default", "flags"],
+        "Rowid" | "NewRowid" => ["cursor", "into register", "", "", ""],
+        "ResultRow" => ["first register", "register count", "", "", ""],
+        "Integer" => ["value", "into register", "", "", ""],
+        "Int64" | "Real" | "String8" | "Blob" => ["", "into register", "", "value", ""],
+        "Null" => ["", "first register", "last register", "", ""],
+        "Copy" | "SCopy" | "Move" => ["from register", "to register", "", "", ""],
+        "MakeRecord" => [
+            "first register",
+            "register count",
+            "into register",
+            "affinity",
+            "",
+        ],
+        "Insert" => [
+            "cursor",
+            "record register",
+            "rowid register",
+            "table",
+            "flags",
+        ],
+        "Delete" => ["cursor", "", "", "table", "flags"],
+        "Function" | "PureFunc" => [
+            "constant mask",
+            "first argument",
+            "into register",
+            "function",
+            "",
+        ],
+        "AggStep" => [
+            "",
+            "first argument",
+            "accumulator",
+            "function",
+            "argument count",
+        ],
+        "AggFinal" => ["accumulator", "argument count", "", "function", ""],
+        "Transaction" => ["database", "write", "schema cookie", "", ""],
+        "Halt" => ["result code", "on error", "", "message", ""],
+        "Goto" | "Init" => ["", "jump to", "", "", ""],
+        "Gosub" => ["return register", "jump to", "", "", ""],
+        "Return" => ["return register", "", "", "", ""],
+        "InitCoroutine" => ["coroutine register", "jump to", "entry", "", ""],
+        "Yield" => ["coroutine register", "jump to", "", "", ""],
+        "Rewind" | "Last" | "Sort" | "SorterSort" | "Next" | "Prev" | "SorterNext" => {
+            ["cursor", "jump to", "", "", ""]
+        }
+        "SeekLT" | "SeekLE" | "SeekGE" | "SeekGT" | "IdxLT" | "IdxLE" | "IdxGT" | "IdxGE" => {
+            ["cursor", "jump to", "first key register", "key count", ""]
+        }
+        "SeekRowid" | "NotExists" => ["cursor", "jump to", "rowid register", "", ""],
+        "Eq" | "Ne" | "Lt" | "Le" | "Gt" | "Ge" => [
+            "left register",
+            "jump to",
+            "right register",
+            "collation",
+            "flags",
+        ],
+        "If" | "IfNot" | "IsNull" | "NotNull" | "IfPos" | "IfNotZero" | "DecrJumpZero" => {
+            ["register", "jump to", "", "", ""]
+        }
+        "Add" | "Subtract" | "Multiply" | "Divide" | "Remainder" | "Concat" => {
+            ["right register", "left register", "into register", "", ""]
+        }
+        _ => ["", "", "", "", ""],
+    }
+}
+
+fn int(row: &[SQLiteValue], idx: usize) -> i64 {
+    match row.get(idx) {
+        Some(SQLiteValue::Integer(value)) => *value,
+        _ => 0,
+    }
+}
+
+fn opcode(row: &[SQLiteValue]) -> &str {
+    match row.get(1) {
+        Some(SQLiteValue::Text(opcode)) => opcode,
+        _ => "",
+    }
+}
+
+/// Indent the bodies of loops, addresses start at 0 so they are also row indexes.
+fn indents(rows: &[Vec<SQLiteValue>]) -> Vec<usize> {
+    let mut indents = vec![0; rows.len()];
+    for (idx, row) in rows.iter().enumerate() {
+        let op = opcode(row);
+        let Ok(target) = usize::try_from(int(row, 3)) else {
+            continue;
+        };
+        if target == 0 || target >= idx {
+            continue;
+        }
+        let is_loop = NEXT_OPCODES.contains(&op)
+            || (op == "Goto"
+                && (YIELD_OPCODES.contains(&opcode(&rows[target])) || int(row, 2) != 0));
+        if is_loop {
+            for indent in &mut indents[target..idx] {
+                *indent += 1;
+            }
+        }
+    }
+    indents
+}
+
+fn get_cell(value: &SQLiteValue, note: &'static str) -> AnyView {
+    let text = match value {
+        SQLiteValue::Null => String::new(),
+        SQLiteValue::Text(text) => text.clone(),
+        value => value.to_string(),
+    };
+    if note.is_empty() {
+        view! { <td class=styles::td>{text}</td> }.into_any()
+    } else {
+        view! {
+            <td class=styles::td>
+                <span class=styles::annotated title=note>
+                    {text}
+                </span>
+            </td>
+        }
+        .into_any()
+    }
+}
+
+fn get_output(
+    number: usize,
+    values: &SQLiteStatementValues,
+    selected: RwSignal<Option<String>>,
+) -> AnyView {
+    let row_id = move |addr: i64| format!("bytecode-{number}-{addr}");
+    let indents = indents(&values.rows);
+
+    let rows = values
+        .rows
+        .iter()
+        .zip(indents)
+        .map(|(row, indent)| {
+            let addr = int(row, 0);
+            let opcode = opcode(row).to_string();
+            let notes = operands(&opcode);
+            let id = row_id(addr);
+            let class = {
+                let id = id.clone();
+                move || {
+                    if selected.read().as_ref() == Some(&id) {
+                        styles::selected
+                    } else {
+                        ""
+                    }
+                }
+            };
+
+            let p2 = if JUMP_OPCODES.contains(&opcode.as_str()) {
+                let target = int(row, 3);
+                let jump = move |_| {
+                    let id = row_id(target);
+                    if let Some(element) = document().get_element_by_id(&id) {
+                        element.scroll_into_view();
+                    }
+                    selected.set(Some(id));
+                };
+                view! {
+                    <td class=styles::td>
+                        <button class=styles::jump title="jump to" on:click=jump>
+                            {target}
+                        </button>
+                    </td>
+                }
+                .into_any()
+            } else {
+                get_cell(row.get(3).unwrap_or(&SQLiteValue::Null), notes[1])
+            };
+
+            let cell =
+                |idx: usize, note| get_cell(row.get(idx).unwrap_or(&SQLiteValue::Null), note);
+
+            view! {
+                <tr id=id class=class>
+                    <td class=styles::td>{addr}</td>
+                    <td class=styles::td style=format!("padding-left: {}ch", 1 + indent * 2)>
+                        {opcode}
+                    </td>
+                    {cell(2, notes[0])}
+                    {p2}
+                    {cell(4, notes[2])}
+                    {cell(5, notes[3])}
+                    {cell(6, notes[4])}
+                    {cell(7, "")}
+                </tr>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <table class=styles::table>
+            <tr>
+                <th class=styles::td>"addr"</th>
+                <th class=styles::td>"opcode"</th>
+                <th class=styles::td title="Usually a cursor, register or value">
+                    "p1"
+                </th>
+                <th class=styles::td title="Usually a jump address or register">
+                    "p2"
+                </th>
+                <th class=styles::td title="Usually a register">
+                    "p3"
+                </th>
+                <th class=styles::td title="A string, key info, function or other operand">
+                    "p4"
+                </th>
+                <th class=styles::td title="Flags">
+                    "p5"
+                </th>
+                <th class=styles::td>"comment"</th>
+            </tr>
+            {rows}
+        </table>
+    }
+    .into_any()
+}
+
+#[component]
+fn Output() -> AnyView {
+    let state = expect_context::<Store<GlobalState>>();
+    let selected = RwSignal::new(None);
+
+    view! {
+        <>
+            <Show
+                when=move || {
+                    state
+                        .bytecode()
+                        .read()
+                        .last()
+                        .is_none_or(|r| !matches!(r, SQLiteStatementResult::Finish { .. }))
+                }
+                fallback=|| ()
+            >
+                <Loader />
+            </Show>
+
+            <>
+                {move || {
+                    state
+                        .bytecode()
+                        .read()
+                        .iter()
+                        .filter(|item| !matches!(item, SQLiteStatementResult::Finish { .. }))
+                        .enumerate()
+                        .map(|(idx, item)| {
+                            let number = idx + 1;
+                            let label = format!("Statement #{number}");
+                            match item {
+                                SQLiteStatementResult::Finish { .. } => unreachable!(),
+                                SQLiteStatementResult::Error { error, .. } => {
+                                    let error = error.to_string();
+                                    view! {
+                                        <Section label=label>
+                                            <pre style="white-space: pre-wrap;">{error}</pre>
+                                        </Section>
+                                    }
+                                        .into_any()
+                                }
+                                SQLiteStatementResult::Step(table) => {
+                                    let sql = table.sql.trim().to_string();
+                                    let output = table
+                                        .values
+                                        .as_ref()
+                                        .map(|values| get_output(number, values, selected));
+                                    view! {
+                                        <Section label=label>
+                                            <pre class=styles::sql>{sql}</pre>
+                                            {output}
+                                        </Section>
+                                    }
+                                        .into_any()
+                                }
+                            }
+                        })
+                        .collect_view()
+                }}
+            </>
+
+            <Show
+                when=move || {
+                    matches!(state.bytecode().read().last(), Some(SQLiteStatementResult::Finish { .. }))
+                }
+                fallback=|| ()
+            >
+                <Header label="Finished".into() />
+            </Show>
+        </>
+    }
+    .into_any()
+}
+
+#[component]
+pub fn Bytecode() -> impl IntoView {
+    view! {
+        <SimplePane>
+            <Output />
+        </SimplePane>
+    }
+}
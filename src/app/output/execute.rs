@@ -3,61 +3,251 @@ use leptos::prelude::*;
 use reactive_stores::Store;
 
 use crate::app::{
-    output::{header::Header, loader::Loader, section::Section, simple_pane::SimplePane},
+    output::{
+        export::ExportMenu, header::Header, loader::Loader, section::Section,
+        simple_pane::SimplePane,
+    },
     state::{GlobalState, GlobalStateStoreFields},
 };
-use crate::{SQLiteStatementResult, SQLiteStatementTable};
+use crate::{SQLiteStatementMetrics, SQLiteStatementResult, SQLiteStatementTable, SQLiteValue};
 
 istyles!(
     styles,
     "assets/module.postcss/output/execute.module.css.map"
 );
 
-fn get_output(table: &SQLiteStatementTable) -> Option<AnyView> {
-    let Some(values) = &table.values else {
-        return None;
-    };
-    Some(
-        view! {
-            <table
-                class=styles::table
-                style="table-layout: fixed; width: 100%; word-wrap: break-word;"
-            >
-                <tr>
-                    {values
-                        .columns
-                        .iter()
-                        .map(|s| {
-                            view! { <th class=styles::tdAndTh>{s.to_string()}</th> }
-                        })
-                        .collect_view()}
-                </tr>
-                {values
+fn get_cell(value: &SQLiteValue) -> AnyView {
+    match value {
+        SQLiteValue::Null => view! { <td class=styles::null>"NULL"</td> }.into_any(),
+        SQLiteValue::Integer(_) | SQLiteValue::Real(_) => {
+            view! { <td class=styles::number>{value.to_string()}</td> }.into_any()
+        }
+        SQLiteValue::Text(text) => {
+            view! { <td class=styles::tdAndTh>{text.clone()}</td> }.into_any()
+        }
+        SQLiteValue::Blob(_) => {
+            view! { <td class=styles::tdAndTh>{value.to_string()}</td> }.into_any()
+        }
+    }
+}
+
+fn get_details(metrics: &SQLiteStatementMetrics) -> String {
+    let mut details = vec![
+        format!("{:.2} ms", metrics.duration),
+        format!("{} VM steps", metrics.vm_steps),
+    ];
+    for (count, name) in [
+        (metrics.full_scan_steps, "full scan steps"),
+        (metrics.sorts, "sorts"),
+        (metrics.autoindexes, "automatic indexes"),
+    ] {
+        if count > 0 {
+            details.push(format!("{count} {name}"));
+        }
+    }
+    if let Some(rowid) = metrics.last_insert_rowid {
+        details.push(format!("last insert rowid {rowid}"));
+    }
+    details.join(" · ")
+}
+
+/// A result of the output, its rows are rendered as they arrive.
+#[derive(Clone)]
+struct Entry {
+    /// Entries of a new run get new keys.
+    run: usize,
+    index: usize,
+    position: Option<[usize; 2]>,
+    /// Statement number, rows of a failed statement are followed by its error.
+    number: usize,
+    rows: RwSignal<usize>,
+    details: RwSignal<Option<String>>,
+}
+
+fn position(item: &SQLiteStatementResult) -> Option<[usize; 2]> {
+    match item {
+        SQLiteStatementResult::Finish { .. } => None,
+        SQLiteStatementResult::Step(table) => Some(table.position),
+        SQLiteStatementResult::Error { position, .. } => Some(*position),
+    }
+}
+
+fn row_count(item: &SQLiteStatementResult) -> usize {
+    match item {
+        SQLiteStatementResult::Step(SQLiteStatementTable {
+            values: Some(values),
+            ..
+        }) => values.rows.len(),
+        _ => 0,
+    }
+}
+
+fn details(item: &SQLiteStatementResult) -> Option<String> {
+    match item {
+        SQLiteStatementResult::Step(table) => table.metrics.as_ref().map(get_details),
+        _ => None,
+    }
+}
+
+/// Follow the output of the state, only new entries and rows are rendered.
+fn track_entries(state: Store<GlobalState>, entries: RwSignal<Vec<Entry>>) {
+    Effect::new(move || {
+        let run = state.output_runs().get();
+        let output = state.output().read();
+
+        // cleared for a new run
+        let stale = entries.with_untracked(|entries| {
+            entries.len() > output.len() || entries.first().is_some_and(|entry| entry.run != run)
+        });
+        if stale {
+            entries.set(vec![]);
+        }
+
+        let known = entries.with_untracked(|entries| {
+            for entry in entries {
+                let item = &output[entry.index];
+                let rows = row_count(item);
+                if entry.rows.get_untracked() != rows {
+                    entry.rows.set(rows);
+                }
+                let details = details(item);
+                if entry.details.get_untracked() != details {
+                    entry.details.set(details);
+                }
+            }
+            entries.len()
+        });
+        if known == output.len() {
+            return;
+        }
+
+        entries.update(|entries| {
+            for (index, item) in output.iter().enumerate().skip(known) {
+                let position = position(item);
+                let number = match entries.last() {
+                    Some(last) if position.is_some() && position != last.position => {
+                        last.number + 1
+                    }
+                    Some(last) => last.number,
+                    None => usize::from(position.is_some()),
+                };
+                entries.push(Entry {
+                    run,
+                    index,
+                    position,
+                    number,
+                    rows: RwSignal::new(row_count(item)),
+                    details: RwSignal::new(details(item)),
+                });
+            }
+        });
+    });
+}
+
+#[component]
+fn Rows(index: usize, rows: RwSignal<usize>) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let columns = state
+        .output()
+        .with_untracked(|output| match &output[index] {
+            SQLiteStatementResult::Step(SQLiteStatementTable {
+                values: Some(values),
+                ..
+            }) => values.columns.clone(),
+            _ => vec![],
+        });
+
+    let cells = move |row: usize| {
+        state
+            .output()
+            .with_untracked(|output| match &output.get(index) {
+                Some(SQLiteStatementResult::Step(SQLiteStatementTable {
+                    values: Some(values),
+                    ..
+                })) => values
                     .rows
-                    .iter()
-                    .map(|row| {
-                        view! {
-                            <tr>
-
-                                {row
-                                    .iter()
-                                    .map(|s| {
-                                        view! { <td class=styles::tdAndTh>{s.to_string()}</td> }
-                                    })
-                                    .collect_view()}
-                            </tr>
-                        }
-                    })
+                    .get(row)
+                    .map(|row| row.iter().map(get_cell).collect_view()),
+                _ => None,
+            })
+    };
+
+    view! {
+        <table class=styles::table style="table-layout: fixed; width: 100%; word-wrap: break-word;">
+            <tr>
+                {columns
+                    .into_iter()
+                    .map(|s| view! { <th class=styles::tdAndTh>{s}</th> })
                     .collect_view()}
-            </table>
-        }
-        .into_any(),
-    )
+            </tr>
+            <For each=move || 0..rows.get() key=|row| *row let:row>
+                <tr>{cells(row)}</tr>
+            </For>
+        </table>
+    }
+}
+
+#[component]
+fn Statement(entry: Entry) -> AnyView {
+    let state = expect_context::<Store<GlobalState>>();
+    let Entry {
+        index,
+        number,
+        rows,
+        details,
+        ..
+    } = entry;
+
+    state
+        .output()
+        .with_untracked(|output| match &output[index] {
+            SQLiteStatementResult::Finish { discarded } => {
+                let label = if *discarded {
+                    "Finished, changes were discarded"
+                } else {
+                    "Finished"
+                };
+                view! { <Header label=label.into() /> }.into_any()
+            }
+            SQLiteStatementResult::Error { error, .. } => {
+                let label = format!("Statement #{number} failed");
+                let error = error.to_string();
+                view! {
+                    <Section label=label>
+                        <pre style="white-space: pre-wrap;">{error}</pre>
+                    </Section>
+                }
+                .into_any()
+            }
+            SQLiteStatementResult::Step(table) => {
+                let label = format!("Statement #{number}");
+                // the first chunk has the columns, a statement without columns only has metrics
+                let output = match (&table.values, &table.metrics) {
+                    (Some(_), _) => view! { <Rows index=index rows=rows /> }.into_any(),
+                    (None, Some(metrics)) => {
+                        format!("{} rows affected", metrics.changes).into_any()
+                    }
+                    (None, None) => return ().into_any(),
+                };
+                let menu = table.values.is_some().then(|| {
+                    let name = format!("statement-{number}");
+                    view! { <ExportMenu index=index name=name /> }.into_any()
+                });
+                view! {
+                    <Section label=label details=details menu=menu>
+                        <p>{output}</p>
+                    </Section>
+                }
+                .into_any()
+            }
+        })
 }
 
 #[component]
 fn Output() -> AnyView {
     let state = expect_context::<Store<GlobalState>>();
+    let entries = RwSignal::new(vec![]);
+    track_entries(state, entries);
 
     view! {
         <>
@@ -67,45 +257,18 @@ fn Output() -> AnyView {
                         .output()
                         .read()
                         .last()
-                        .is_none_or(|r| !matches!(r, SQLiteStatementResult::Finish))
+                        .is_none_or(|r| !matches!(r, SQLiteStatementResult::Finish { .. }))
                 }
                 fallback=|| ()
             >
                 <Loader />
             </Show>
 
-            <>
-                {move || {
-                    state
-                        .output()
-                        .read()
-                        .iter()
-                        .enumerate()
-                        .map(|(idx, item)| {
-                            match &item {
-                                SQLiteStatementResult::Finish => {
-
-                                    view! { <Header label="Finished".into() /> }
-                                        .into_any()
-                                }
-                                SQLiteStatementResult::Step(table) => {
-                                    let label = format!("Statement #{}", idx + 1);
-                                    if let Some(output) = get_output(table) {
-                                        view! {
-                                            <Section label=label>
-                                                <p>{output}</p>
-                                            </Section>
-                                        }
-                                            .into_any()
-                                    } else {
-                                        ().into_any()
-                                    }
-                                }
-                            }
-                        })
-                        .collect_view()
-                }}
-            </>
+            <For
+                each=move || entries.get()
+                key=|entry| (entry.run, entry.index)
+                children=|entry| view! { <Statement entry=entry /> }
+            />
         </>
     }
     .into_any()
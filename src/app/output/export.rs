@@ -0,0 +1,267 @@
+use istyles::istyles;
+use leptos::prelude::*;
+use reactive_stores::Store;
+use wasm_bindgen_futures::{JsFuture, spawn_local};
+use web_sys::Event;
+
+use crate::{
+    FragileComfirmed, SQLiteStatementResult, SQLiteStatementTable, SQLiteStatementValues,
+    SQLiteValue,
+    app::{Exported, GlobalState, GlobalStateStoreFields},
+    identifier,
+};
+
+istyles!(styles, "assets/module.postcss/output/export.module.css.map");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    Tsv,
+    Json,
+    Ndjson,
+    Markdown,
+    Insert,
+}
+
+impl ExportFormat {
+    const ALL: [ExportFormat; 6] = [
+        ExportFormat::Csv,
+        ExportFormat::Tsv,
+        ExportFormat::Json,
+        ExportFormat::Ndjson,
+        ExportFormat::Markdown,
+        ExportFormat::Insert,
+    ];
+
+    fn value(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Tsv => "TSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Ndjson => "NDJSON",
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Insert => "INSERT",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Tsv => "tsv",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Insert => "sql",
+        }
+    }
+
+    fn from_select(s: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.value() == s)
+            .unwrap_or(ExportFormat::Csv)
+    }
+}
+
+/// A field of csv and markdown, blobs are written in hex.
+fn text(value: &SQLiteValue) -> String {
+    match value {
+        SQLiteValue::Null => String::new(),
+        SQLiteValue::Text(text) => text.clone(),
+        SQLiteValue::Blob(blob) => hex::encode(blob),
+        SQLiteValue::Integer(_) | SQLiteValue::Real(_) => value.to_string(),
+    }
+}
+
+fn json(value: &SQLiteValue) -> serde_json::Value {
+    match value {
+        SQLiteValue::Null => serde_json::Value::Null,
+        SQLiteValue::Integer(number) => serde_json::Value::from(*number),
+        // infinite numbers are null
+        SQLiteValue::Real(number) => serde_json::Value::from(*number),
+        SQLiteValue::Text(text) => serde_json::Value::from(text.as_str()),
+        SQLiteValue::Blob(blob) => serde_json::Value::from(hex::encode(blob)),
+    }
+}
+
+fn delimited(values: &SQLiteStatementValues, delimiter: u8) -> String {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(vec![]);
+    let rows = values
+        .rows
+        .iter()
+        .map(|row| row.iter().map(text).collect::<Vec<_>>());
+    let result = std::iter::once(values.columns.clone())
+        .chain(rows)
+        .try_for_each(|record| writer.write_record(record));
+    if let Err(err) = result {
+        log::error!("Failed to write csv: {err}");
+    }
+    writer
+        .into_inner()
+        .map(|data| String::from_utf8_lossy(&data).into_owned())
+        .unwrap_or_default()
+}
+
+/// An object with the keys in the order of the columns.
+fn json_object(columns: &[String], row: &[SQLiteValue]) -> String {
+    let fields = columns
+        .iter()
+        .zip(row)
+        .map(|(column, value)| {
+            format!(
+                "{}:{}",
+                serde_json::Value::from(column.as_str()),
+                json(value)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn markdown(values: &SQLiteStatementValues) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', "<br>");
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+    let mut table = line(values.columns.iter().map(|column| cell(column)).collect());
+    table += &line(vec!["---".to_string(); values.columns.len()]);
+    for row in &values.rows {
+        table += &line(
+            row.iter()
+                .map(|value| match value {
+                    SQLiteValue::Null => "NULL".to_string(),
+                    value => cell(&text(value)),
+                })
+                .collect(),
+        );
+    }
+    table
+}
+
+fn insert(values: &SQLiteStatementValues, table: &str) -> String {
+    let columns = values
+        .columns
+        .iter()
+        .map(|column| identifier(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    values
+        .rows
+        .iter()
+        .map(|row| {
+            let row = row.iter().map(ToString::to_string).collect::<Vec<_>>();
+            format!(
+                "INSERT INTO {} ({columns}) VALUES ({});\n",
+                identifier(table),
+                row.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// Serialize the rows, `table` is only used by INSERT statements.
+fn export(values: &SQLiteStatementValues, format: ExportFormat, table: &str) -> String {
+    let objects = || {
+        values
+            .rows
+            .iter()
+            .map(|row| json_object(&values.columns, row))
+    };
+    match format {
+        ExportFormat::Csv => delimited(values, b','),
+        ExportFormat::Tsv => delimited(values, b'\t'),
+        ExportFormat::Json => format!("[\n{}\n]\n", objects().collect::<Vec<_>>().join(",\n")),
+        ExportFormat::Ndjson => objects().map(|object| object + "\n").collect(),
+        ExportFormat::Markdown => markdown(values),
+        ExportFormat::Insert => insert(values, table),
+    }
+}
+
+/// Export the rows of the statement at `index` of the output, `name` is the default name of the file and table.
+#[component]
+pub fn ExportMenu(index: usize, name: String) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let format = RwSignal::new(ExportFormat::Csv);
+    let name = StoredValue::new(name);
+
+    // `None` if cancelled
+    let exported = move || {
+        let format = format.get_untracked();
+        let table = if format == ExportFormat::Insert {
+            window()
+                .prompt_with_message_and_default("Insert into table:", &name.get_value())
+                .ok()
+                .flatten()?
+        } else {
+            String::new()
+        };
+        // read when exporting, the rows are still streamed while the menu is shown
+        state
+            .output()
+            .with_untracked(|output| match output.get(index) {
+                Some(SQLiteStatementResult::Step(SQLiteStatementTable {
+                    values: Some(values),
+                    ..
+                })) => Some(export(values, format, &table)),
+                _ => None,
+            })
+    };
+
+    let on_download = move |_| {
+        let Some(exported) = exported() else {
+            return;
+        };
+        let filename = format!(
+            "{}.{}",
+            name.get_value(),
+            format.get_untracked().extension()
+        );
+        state.exported().set(Some(Exported {
+            filename,
+            data: FragileComfirmed::new(js_sys::Uint8Array::from(exported.as_bytes())),
+        }));
+    };
+
+    let on_copy = move |_| {
+        let Some(exported) = exported() else {
+            return;
+        };
+        spawn_local(async move {
+            if let Err(err) =
+                JsFuture::from(window().navigator().clipboard().write_text(&exported)).await
+            {
+                log::error!("Failed to write the export to clipboard: {err:?}");
+            }
+        });
+    };
+
+    let on_change = move |ev: Event| {
+        format.set(ExportFormat::from_select(&event_target_value(&ev)));
+    };
+
+    let options = ExportFormat::ALL
+        .into_iter()
+        .map(|option| {
+            view! {
+                <option value=option.value() selected=move || *format.read() == option>
+                    {option.value()}
+                </option>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div class=styles::container>
+            <select class=styles::select on:change=on_change>
+                {options}
+            </select>
+            <button class=styles::button on:click=on_download>
+                "Download"
+            </button>
+            <button class=styles::button on:click=on_copy>
+                "Copy"
+            </button>
+        </div>
+    }
+}
@@ -4,6 +4,19 @@ use leptos::prelude::*;
 istyles!(styles, "assets/module.postcss/output/header.module.css.map");
 
 #[component]
-pub fn Header(label: String) -> impl IntoView {
-    view! { <span class=styles::container>{label}</span> }
+pub fn Header(
+    label: String,
+    /// Updated while the statement runs.
+    #[prop(into, optional)]
+    details: Signal<Option<String>>,
+) -> impl IntoView {
+    let details = move || {
+        details
+            .get()
+            .map(|details| view! { <span class=styles::details>{details}</span> })
+    };
+    view! {
+        <span class=styles::container>{label}</span>
+        {details}
+    }
 }
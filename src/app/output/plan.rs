@@ -0,0 +1,80 @@
+use istyles::istyles;
+use leptos::prelude::*;
+use reactive_stores::Store;
+
+use crate::QueryPlanNode;
+use crate::app::{
+    output::{section::Section, simple_pane::SimplePane},
+    state::{GlobalState, GlobalStateStoreFields},
+};
+
+istyles!(styles, "assets/module.postcss/output/plan.module.css.map");
+
+fn get_tree(nodes: &[QueryPlanNode], parent: i64) -> AnyView {
+    let children = nodes
+        .iter()
+        .filter(|node| node.parent == parent)
+        .map(|node| {
+            let detail = node.detail.clone();
+            if nodes.iter().any(|child| child.parent == node.id) {
+                view! {
+                    <li>
+                        <details open=true>
+                            <summary>{detail}</summary>
+                            {get_tree(nodes, node.id)}
+                        </details>
+                    </li>
+                }
+                .into_any()
+            } else {
+                view! { <li class=styles::leaf>{detail}</li> }.into_any()
+            }
+        })
+        .collect_view();
+
+    view! { <ul class=styles::tree>{children}</ul> }.into_any()
+}
+
+#[component]
+fn Output() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+
+    view! {
+        {move || {
+            state
+                .query_plan()
+                .read()
+                .iter()
+                .flatten()
+                .enumerate()
+                .map(|(idx, plan)| {
+                    let label = format!("Statement #{}", idx + 1);
+                    let sql = plan.sql.clone();
+                    let tree = match &plan.nodes {
+                        Ok(nodes) if nodes.is_empty() => "No query plan".into_any(),
+                        Ok(nodes) => get_tree(nodes, 0),
+                        Err(error) => {
+                            let error = error.to_string();
+                            view! { <pre style="white-space: pre-wrap;">{error}</pre> }.into_any()
+                        }
+                    };
+                    view! {
+                        <Section label=label>
+                            <pre class=styles::sql>{sql}</pre>
+                            {tree}
+                        </Section>
+                    }
+                })
+                .collect_view()
+        }}
+    }
+}
+
+#[component]
+pub fn Plan() -> impl IntoView {
+    view! {
+        <SimplePane>
+            <Output />
+        </SimplePane>
+    }
+}
@@ -9,10 +9,18 @@ istyles!(
 );
 
 #[component]
-pub fn Section(label: String, children: Children) -> impl IntoView {
+pub fn Section(
+    label: String,
+    #[prop(into, optional)] details: Signal<Option<String>>,
+    /// Shown below the header, e.g. to export the rows.
+    #[prop(default = None)]
+    menu: Option<AnyView>,
+    children: Children,
+) -> impl IntoView {
     view! {
         <div>
-            <Header label=label />
+            <Header label=label details=details />
+            {menu}
             <p>{children()}</p>
         </div>
     }
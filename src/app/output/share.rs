@@ -5,7 +5,15 @@ use leptos::prelude::*;
 use reactive_stores::Store;
 use wasm_bindgen_futures::{JsFuture, spawn_local};
 
-use crate::app::{GlobalState, GlobalStateStoreFields, icon::clipboard_icon};
+use crate::{
+    WorkerRequest, WorkerResponse,
+    app::{
+        GlobalState, GlobalStateStoreFields,
+        icon::clipboard_icon,
+        shared::{MAX_DB_LINK, SharedPayload},
+    },
+    request,
+};
 
 istyles!(styles, "assets/module.postcss/output/share.module.css.map");
 
@@ -56,11 +64,81 @@ fn EmbeddedLinks() -> impl IntoView {
     }
 }
 
+/// A link with the code and a snapshot of the main database.
+async fn data_link(state: Store<GlobalState>) -> Result<String, String> {
+    if state.encryption().read_untracked().is_some() {
+        return Err("Encrypted databases can not be shared.".into());
+    }
+    let code = state
+        .editor()
+        .read_untracked()
+        .as_ref()
+        .map_or_else(|| state.sql().get_untracked(), |editor| editor.get_value());
+
+    let db = match request(state, WorkerRequest::DownloadDb { compressed: false }).await {
+        Ok(WorkerResponse::DownloadDb(Ok(resp))) => resp.data.to_vec(),
+        Ok(WorkerResponse::DownloadDb(Err(err))) | Err(err) => return Err(format!("{err}")),
+        Ok(resp) => {
+            log::error!("Unexpected response to download request: {resp:?}");
+            return Err("Unexpected response".into());
+        }
+    };
+    let mut payload = SharedPayload::new(code, state.parameters().get_untracked());
+    payload.db = Some(db);
+    payload.href()
+}
+
+#[component]
+fn DataLinks() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let link = RwSignal::new(None::<Result<String, String>>);
+
+    let share = move |_| {
+        spawn_local(async move {
+            link.set(Some(data_link(state).await));
+        });
+    };
+
+    let shared = move || match link.get() {
+        Some(Ok(href)) => {
+            let size = href.len().div_ceil(1024);
+            let shared = href.clone();
+            view! {
+                <Copied shared=move || shared.clone() href=move || href.clone()>
+                    "Embedded code and database in link"
+                </Copied>
+                <p class=styles::warning>
+                    {format!(
+                        "Anyone with the link can read the data. The link is {size} KiB, \
+                         some chat tools may truncate it.",
+                    )}
+                </p>
+            }
+            .into_any()
+        }
+        Some(Err(err)) => view! { <p class=styles::warning>{err}</p> }.into_any(),
+        None => view! {
+            <p class=styles::warning>
+                {format!("The database must be at most {} KiB once encoded.", MAX_DB_LINK / 1024)}
+            </p>
+        }
+        .into_any(),
+    };
+
+    view! {
+        <button class=styles::share on:click=share>
+            "Share with data"
+        </button>
+        {shared}
+    }
+}
+
 #[component]
 pub fn Share() -> impl IntoView {
     view! {
         <>
             <EmbeddedLinks />
+            <DataLinks />
         </>
     }
 }
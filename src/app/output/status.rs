@@ -35,6 +35,12 @@ pub fn Status() -> impl IntoView {
                             "If database disk image is malformed, change the context option and run it once."
                         }
                         SQLitendError::Prepare(_) => "Check if the syntax is correct.",
+                        SQLitendError::Explain(_) => {
+                            "The statement could not be switched to explain mode, try running EXPLAIN directly."
+                        }
+                        SQLitendError::Bind(_) => {
+                            "Check the type and value of the parameter in the parameter form."
+                        }
                         SQLitendError::UnsupportColumnType(_) => {
                             "An unsupported type was encountered, please create an issue on github."
                         }
@@ -48,8 +54,30 @@ pub fn Status() -> impl IntoView {
                     WorkerError::InvaildState => {
                         "SQLite is in an abnormal state when executing sql, change the context option and run it once."
                     }
-                    WorkerError::LoadDb(_) => "Check whether the imported DB is a SQLite3 file.",
+                    WorkerError::LoadDb(_) => {
+                        "Check whether the imported DB is a SQLite3 file, and the key and cipher if it is encrypted."
+                    }
                     WorkerError::DownloadDb(_) => "It may be caused by OOM.",
+                    WorkerError::ManageDb(_) => "Refresh the database list and try again.",
+                    WorkerError::AttachDb(_) => {
+                        "Check whether the attached DB is a SQLite3 file and the alias is not in use."
+                    }
+                    WorkerError::Interrupted => {
+                        "Stopped by the user. Statements before the interrupted one have already been executed, \
+                        unless the worker had to be restarted with a memory DB."
+                    }
+                    WorkerError::ImportCsv(_) => {
+                        "Check the delimiter, header and quote options of the csv import, nothing was imported."
+                    }
+                    WorkerError::ImportJson(_) => {
+                        "Each record must be an object, in a json array or on its own line. Nothing was imported."
+                    }
+                    WorkerError::Encryption(_) => {
+                        "Check the key, cipher and legacy parameter in the encryption options."
+                    }
+                    WorkerError::Crashed => {
+                        "The worker panicked, see the console for details. The database has been reopened, changes to a memory DB are lost."
+                    }
                     WorkerError::OpfsSAHPoolOpened => OPFS_SAH_POOL_OPENED_DETAILS,
                 },
                 SQLightError::AceEditor(ace_editor) => match ace_editor {
@@ -97,17 +125,35 @@ pub fn Status() -> impl IntoView {
                             view! { <p>"Processing failed"</p> }.into_any()
                         }
                     }
-                    None => view! { <p>"Processing..."</p> }.into_any(),
+                    None => match progress.processed {
+                        Some(processed) => {
+                            let processing =
+                                format!("Processing: {processed} of {} bytes", progress.total);
+                            view! { <p>{processing}</p> }.into_any()
+                        }
+                        None => view! { <p>"Processing..."</p> }.into_any(),
+                    },
                 }
             } else {
                 ().into_any()
             };
 
+            let imported = progress.imported.as_ref().map(|imported| {
+                let imported = format!(
+                    "Imported {} rows into {} table {}",
+                    imported.rows,
+                    if imported.created { "the new" } else { "the" },
+                    imported.table
+                );
+                view! { <p>{imported}</p> }
+            });
+
             view! {
                 <p>{filename}</p>
                 <p>{loading}</p>
                 {status}
                 {process}
+                {imported}
             }
             .into_any()
         } else {
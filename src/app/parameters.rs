@@ -0,0 +1,153 @@
+use istyles::istyles;
+use leptos::prelude::*;
+use reactive_stores::Store;
+use web_sys::SubmitEvent;
+
+use crate::{
+    ParameterKind, SQLiteParameter, WorkerRequest,
+    app::{
+        GlobalState, GlobalStateStoreFields, PendingRun,
+        button_set::{Button, ButtonSet},
+        header::start_run,
+    },
+    send_request,
+};
+
+istyles!(styles, "assets/module.postcss/parameters.module.css.map");
+
+#[component]
+fn Form(names: Vec<String>) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+
+    // start with the last values of the same names
+    let values = RwSignal::new(
+        names
+            .into_iter()
+            .map(|name| {
+                state
+                    .parameters()
+                    .read_untracked()
+                    .iter()
+                    .find(|param| param.name == name)
+                    .cloned()
+                    .unwrap_or(SQLiteParameter {
+                        name,
+                        kind: ParameterKind::Text,
+                        value: String::new(),
+                    })
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    let submit = move || {
+        let values = values.get_untracked();
+        state.parameters().update(|parameters| {
+            parameters.retain(|param| !values.iter().any(|value| value.name == param.name));
+            parameters.extend(values.iter().cloned());
+        });
+        if let Some(pending) = state.pending_run().write().take() {
+            match pending.options {
+                Some(mut options) => {
+                    options.asked = pending.names;
+                    start_run(state, options);
+                }
+                None => send_request(state, WorkerRequest::ParameterValues(Some(values))),
+            }
+        }
+    };
+
+    let cancel = move || {
+        if let Some(PendingRun { options: None, .. }) = state.pending_run().write().take() {
+            // stops the paused run
+            send_request(state, WorkerRequest::ParameterValues(None));
+        }
+    };
+
+    let on_submit = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        submit();
+    };
+
+    let rows = move || {
+        values
+            .read_untracked()
+            .iter()
+            .enumerate()
+            .map(|(idx, param)| {
+                let kind = move || values.read().get(idx).map(|param| param.kind);
+                let on_kind = move |ev| {
+                    let kind = ParameterKind::from_select(&event_target_value(&ev));
+                    values.update(|values| values[idx].kind = kind);
+                };
+                let on_value = move |ev| {
+                    let value = event_target_value(&ev);
+                    values.update(|values| values[idx].value = value);
+                };
+                let placeholder = move || match kind() {
+                    Some(ParameterKind::Blob) => "hex",
+                    _ => "",
+                };
+                let options = ParameterKind::ALL
+                    .into_iter()
+                    .map(|option| {
+                        view! {
+                            <option value=option.value() selected=option == param.kind>
+                                {option.value()}
+                            </option>
+                        }
+                    })
+                    .collect_view();
+
+                view! {
+                    <tr>
+                        <td class=styles::name>{param.name.clone()}</td>
+                        <td>
+                            <select class=styles::select on:change=on_kind>
+                                {options}
+                            </select>
+                        </td>
+                        <td>
+                            <input
+                                class=styles::value
+                                prop:value=param.value.clone()
+                                placeholder=placeholder
+                                disabled=move || kind() == Some(ParameterKind::Null)
+                                on:input=on_value
+                            />
+                        </td>
+                    </tr>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <form class=styles::container on:submit=on_submit>
+            <div class=styles::title>"Parameters"</div>
+            <table>{rows}</table>
+            // submitted with enter
+            <input type="submit" hidden />
+            <ButtonSet>
+                <Button is_primary=true is_small=true on_click=move |_| submit()>
+                    "Run"
+                </Button>
+                <Button is_small=true on_click=move |_| cancel()>
+                    "Cancel"
+                </Button>
+            </ButtonSet>
+        </form>
+    }
+}
+
+#[component]
+pub fn Parameters() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+
+    move || {
+        state
+            .pending_run()
+            .read()
+            .as_ref()
+            .map(|pending| view! { <Form names=pending.names.clone() /> })
+    }
+}
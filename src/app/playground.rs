@@ -16,6 +16,8 @@ use crate::{
         editor::Editor,
         header::Header,
         output::{Output, change_focus},
+        parameters::Parameters,
+        schema::Schema,
         state::{GlobalState, GlobalStateStoreFields, Orientation, Theme},
     },
 };
@@ -34,6 +36,7 @@ pub fn Playground() -> impl IntoView {
     handle_automic_orientation(state);
     handle_save_state(state);
     handle_import_progress(state);
+    handle_query_plan(state);
     handle_ace_config(state);
     handle_embed_query_result(state);
 
@@ -50,13 +53,24 @@ fn handle_embed_query_result(state: Store<GlobalState>) {
     const MARK: &str = "-- R:";
 
     Effect::new(move || {
+        // wait until all statements have been streamed
+        if !matches!(
+            state.embed().read().last(),
+            Some(SQLiteStatementResult::Finish { .. })
+        ) {
+            return;
+        }
+
         let mut sqls = vec![];
 
         for result in &*state.embed().read() {
             let mut table_s = Table::new();
 
             match result {
-                SQLiteStatementResult::Finish => continue,
+                // embedding never continues on error
+                SQLiteStatementResult::Finish { .. } | SQLiteStatementResult::Error { .. } => {
+                    continue;
+                }
                 SQLiteStatementResult::Step(table) => {
                     let sql = table.sql.trim().to_string();
 
@@ -65,7 +79,9 @@ fn handle_embed_query_result(state: Store<GlobalState>) {
                             values.columns.iter().map(|s| Cell::new(s)).collect(),
                         ));
                         for row in &values.rows {
-                            table_s.add_row(Row::new(row.iter().map(|s| Cell::new(s)).collect()));
+                            table_s.add_row(Row::new(
+                                row.iter().map(|v| Cell::new(&v.to_string())).collect(),
+                            ));
                         }
 
                         let mut result = table_s
@@ -94,10 +110,10 @@ fn handle_embed_query_result(state: Store<GlobalState>) {
             }
         }
 
-        if !sqls.is_empty() {
-            if let Some(editor) = &*state.editor().read_untracked() {
-                editor.set_value(sqls.join("\n\n"));
-            }
+        if !sqls.is_empty()
+            && let Some(editor) = &*state.editor().read_untracked()
+        {
+            editor.set_value(sqls.join("\n\n"));
         }
     });
 }
@@ -140,10 +156,18 @@ fn handle_save_state(state: Store<GlobalState>) {
         state.editor_config().track();
         state.orientation().track();
         state.theme().track();
-        state.keep_ctx().track();
+        state.run_mode().track();
         state.sql().track();
         state.run_selected_sql().track();
         state.multiple_ciphers().track();
+        state.continue_on_error().track();
+        state.show_schema().track();
+        state.filename().track();
+        state.attachments().track();
+        state.parameters().track();
+        state.snapshot_limit().track();
+        state.csv_options().track();
+        state.script_name().track();
 
         state.read_untracked().save();
     });
@@ -165,6 +189,14 @@ fn handle_import_progress(state: Store<GlobalState>) {
     });
 }
 
+fn handle_query_plan(state: Store<GlobalState>) {
+    Effect::new(move || {
+        if state.query_plan().read().is_some() {
+            change_focus(state, Some(Focus::Plan));
+        }
+    });
+}
+
 fn handle_system_theme(state: Store<GlobalState>) {
     Effect::new(move || {
         let theme = match state.theme().read().value() {
@@ -318,7 +350,17 @@ fn ResizableArea() -> impl IntoView {
     view! {
         <div node_ref=node_ref class=gird_style>
             <div class=styles::editor>
+                <Show when=move || *state.show_schema().read() fallback=|| ()>
+                    <div class=styles::schema>
+                        <Schema />
+                    </div>
+                </Show>
                 <Editor />
+                <Show when=move || state.pending_run().read().is_some() fallback=|| ()>
+                    <div class=styles::parameters>
+                        <Parameters />
+                    </div>
+                </Show>
             </div>
             <Show when=move || state.read().is_focus() fallback=|| ()>
                 <div node_ref=drag_handle class=handle_outer_style>
@@ -0,0 +1,156 @@
+use istyles::istyles;
+use leptos::prelude::*;
+use reactive_stores::Store;
+use web_sys::MouseEvent;
+
+use crate::{
+    SchemaObject,
+    app::{GlobalState, GlobalStateStoreFields},
+    identifier,
+};
+
+istyles!(styles, "assets/module.postcss/schema.module.css.map");
+
+/// The name of the object, prefixed with its schema unless it is in main.
+fn qualified(object: &SchemaObject, name: impl Fn(&str) -> String) -> String {
+    if object.schema == "main" {
+        name(&object.name)
+    } else {
+        format!("{}.{}", name(&object.schema), name(&object.name))
+    }
+}
+
+fn get_columns(object: &SchemaObject) -> AnyView {
+    let columns = object
+        .columns
+        .iter()
+        .map(|column| {
+            let mut details = vec![];
+            if !column.decl_type.is_empty() {
+                details.push(column.decl_type.clone());
+            }
+            if column.primary_key {
+                details.push("PK".into());
+            }
+            if column.not_null {
+                details.push("NOT NULL".into());
+            }
+            if column.generated {
+                details.push("GENERATED".into());
+            }
+            if let Some(default) = &column.default {
+                details.push(format!("= {default}"));
+            }
+            view! {
+                <li>
+                    {column.name.clone()}
+                    <span class=styles::details>{details.join(" ")}</span>
+                </li>
+            }
+        })
+        .collect_view();
+
+    let foreign_keys = object
+        .foreign_keys
+        .iter()
+        .map(|fk| {
+            let to = fk
+                .to
+                .as_deref()
+                .map(|to| format!("({to})"))
+                .unwrap_or_default();
+            view! {
+                <li class=styles::details>
+                    {format!("{} → {}{to}", fk.from, fk.table)}
+                </li>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <ul class=styles::list>
+            {columns}
+            {foreign_keys}
+        </ul>
+    }
+    .into_any()
+}
+
+fn get_object(object: &SchemaObject) -> AnyView {
+    let state = expect_context::<Store<GlobalState>>();
+    let title = object.sql.clone().unwrap_or_default();
+
+    match object.kind.as_str() {
+        "table" | "view" => {
+            let sql = format!("SELECT * FROM {} LIMIT 100", qualified(object, identifier));
+            let on_click = move |ev: MouseEvent| {
+                // do not toggle the details
+                ev.prevent_default();
+                if let Some(editor) = &*state.editor().read_untracked() {
+                    editor.insert(&sql);
+                }
+            };
+            view! {
+                <li>
+                    <details>
+                        <summary title=title>
+                            <button class=styles::name on:click=on_click>
+                                {qualified(object, str::to_string)}
+                            </button>
+                        </summary>
+                        {get_columns(object)}
+                    </details>
+                </li>
+            }
+            .into_any()
+        }
+        _ => view! {
+            <li class=styles::leaf title=title>
+                {qualified(object, str::to_string)}
+                <span class=styles::details>{format!("on {}", object.table)}</span>
+            </li>
+        }
+        .into_any(),
+    }
+}
+
+#[component]
+pub fn Schema() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+
+    let groups = move || {
+        let schema = state.schema().read();
+        let Some(schema) = schema.as_ref() else {
+            return view! { <p class=styles::details>"Loading..."</p> }.into_any();
+        };
+        if schema.is_empty() {
+            return view! { <p class=styles::details>"The database is empty."</p> }.into_any();
+        }
+
+        [
+            ("table", "Tables"),
+            ("view", "Views"),
+            ("index", "Indexes"),
+            ("trigger", "Triggers"),
+        ]
+        .into_iter()
+        .filter(|(kind, _)| schema.iter().any(|object| object.kind == *kind))
+        .map(|(kind, label)| {
+            let objects = schema
+                .iter()
+                .filter(|object| object.kind == kind)
+                .map(get_object)
+                .collect_view();
+            view! {
+                <details open=true>
+                    <summary class=styles::group>{label}</summary>
+                    <ul class=styles::list>{objects}</ul>
+                </details>
+            }
+        })
+        .collect_view()
+        .into_any()
+    };
+
+    view! { <div class=styles::container>{groups}</div> }
+}
@@ -0,0 +1,137 @@
+use std::io::{Read, Write};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use leptos::prelude::window;
+use serde::{Deserialize, Serialize};
+use web_sys::{Url, UrlSearchParams};
+
+use crate::SQLiteParameter;
+
+/// The version of the payloads written by this build.
+const VERSION: u32 = 1;
+
+/// The key of the payload in the fragment, e.g. `#share=...`.
+const FRAGMENT_KEY: &str = "share";
+
+/// The key of the database snapshot in the fragment, next to the payload.
+const DB_KEY: &str = "db";
+
+/// The limit of the encoded database snapshot, longer links get truncated by chat tools.
+pub const MAX_DB_LINK: usize = 64 * 1024;
+
+fn deflate(data: &[u8]) -> Result<String, String> {
+    let mut encoder = DeflateEncoder::new(vec![], Compression::best());
+    encoder.write_all(data).map_err(|err| format!("{err}"))?;
+    let deflated = encoder.finish().map_err(|err| format!("{err}"))?;
+    Ok(URL_SAFE_NO_PAD.encode(deflated))
+}
+
+fn inflate(encoded: &str) -> Result<Vec<u8>, String> {
+    let deflated = URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|err| format!("{err}"))?;
+    let mut data = vec![];
+    DeflateDecoder::new(deflated.as_slice())
+        .read_to_end(&mut data)
+        .map_err(|err| format!("{err}"))?;
+    Ok(data)
+}
+
+/// What a share link carries, deflated and base64url encoded into the fragment.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedPayload {
+    /// Payloads of a newer version are rejected.
+    pub version: u32,
+    pub code: String,
+    #[serde(default)]
+    pub parameters: Vec<SQLiteParameter>,
+    /// A snapshot of the main database, encoded apart to keep the bytes out of the json.
+    #[serde(skip)]
+    pub db: Option<Vec<u8>>,
+}
+
+impl SharedPayload {
+    pub fn new(code: String, parameters: Vec<SQLiteParameter>) -> Self {
+        Self {
+            version: VERSION,
+            code,
+            parameters,
+            db: None,
+        }
+    }
+
+    fn decode(encoded: &str) -> Result<Self, String> {
+        let json = inflate(encoded)?;
+        let payload =
+            serde_json::from_slice::<SharedPayload>(&json).map_err(|err| format!("{err}"))?;
+        if payload.version > VERSION {
+            return Err(format!(
+                "version {} is newer than {VERSION}",
+                payload.version
+            ));
+        }
+        Ok(payload)
+    }
+
+    /// The current page with the payload in the fragment.
+    ///
+    /// Fails if the encoded database exceeds [`MAX_DB_LINK`].
+    pub fn href(&self) -> Result<String, String> {
+        let href = window()
+            .location()
+            .href()
+            .map_err(|err| format!("{err:?}"))?;
+        let url = Url::new(&href).map_err(|err| format!("{err:?}"))?;
+        let json = serde_json::to_vec(self).map_err(|err| format!("{err}"))?;
+        let mut hash = format!("{FRAGMENT_KEY}={}", deflate(&json)?);
+        if let Some(db) = &self.db {
+            let encoded = deflate(db)?;
+            if encoded.len() > MAX_DB_LINK {
+                return Err(format!(
+                    "The database is {} KiB once encoded, only {} KiB fit in a link.",
+                    encoded.len().div_ceil(1024),
+                    MAX_DB_LINK / 1024
+                ));
+            }
+            hash += &format!("&{DB_KEY}={encoded}");
+        }
+        // legacy links put the code in the query
+        url.set_search("");
+        url.set_hash(&hash);
+        Ok(url.href())
+    }
+
+    /// The payload of the link the page was opened with, also accepts legacy `?code=` links.
+    pub fn from_location() -> Option<Self> {
+        let location = window().location();
+
+        let hash = location.hash().ok()?;
+        let fragment =
+            UrlSearchParams::new_with_str(hash.strip_prefix('#').unwrap_or(&hash)).ok()?;
+        if let Some(encoded) = fragment.get(FRAGMENT_KEY) {
+            match Self::decode(&encoded) {
+                Ok(mut payload) => {
+                    payload.db = fragment.get(DB_KEY).and_then(|db| {
+                        inflate(&db)
+                            .inspect_err(|err| {
+                                log::error!("Failed to decode the shared database: {err}")
+                            })
+                            .ok()
+                    });
+                    return Some(payload);
+                }
+                Err(err) => log::error!("Failed to decode the shared link: {err}"),
+            }
+        }
+
+        let search = location.search().ok()?;
+        let params = UrlSearchParams::new_with_str(&search).ok()?;
+        let code = params.get("code")?;
+        let parameters = params
+            .get("params")
+            .and_then(|params| serde_json::from_str(&params).ok())
+            .unwrap_or_default();
+        Some(Self::new(code, parameters))
+    }
+}
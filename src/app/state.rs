@@ -7,7 +7,10 @@ use reactive_stores::Store;
 use serde::{Deserialize, Serialize};
 use web_sys::MediaQueryList;
 
-use crate::{FragileComfirmed, SQLightError, SQLiteStatementResult};
+use crate::{
+    Attachment, CsvOptions, Encryption, FragileComfirmed, ImportedTable, RunMode, RunOptions,
+    SQLightError, SQLiteParameter, SQLiteQueryPlan, SQLiteStatementResult, SchemaObject,
+};
 
 const DEFAULT_CODE: &str = "PRAGMA page_size=4096;
 
@@ -31,10 +34,31 @@ pub struct GlobalState {
     editor_config: EditorConfig,
     orientation: Orientation,
     theme: Theme,
-    keep_ctx: bool,
+    #[serde(default)]
+    run_mode: RunMode,
     sql: String,
     run_selected_sql: bool,
     multiple_ciphers: bool,
+    #[serde(default)]
+    continue_on_error: bool,
+    #[serde(default)]
+    show_schema: bool,
+    /// The opened database of the VFS.
+    #[serde(default = "default_filename")]
+    filename: String,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// MiB kept for snapshots of the db before each run.
+    #[serde(default = "default_snapshot_limit")]
+    snapshot_limit: u32,
+    /// Values of the parameter form for the sql.
+    #[serde(default)]
+    parameters: Vec<SQLiteParameter>,
+    #[serde(default)]
+    csv_options: CsvOptions,
+    /// The file the code in the editor was opened from or saved to.
+    #[serde(default)]
+    script_name: Option<String>,
     // runtime state below
     #[serde(skip)]
     editor: Option<Editor>,
@@ -49,15 +73,44 @@ pub struct GlobalState {
     #[serde(skip)]
     show_something: bool,
     #[serde(skip)]
+    running: bool,
+    /// Counts the responses to opening the database, e.g. after the key changed.
+    #[serde(skip)]
+    opens: usize,
+    #[serde(skip)]
+    pending_run: Option<PendingRun>,
+    /// Only kept in memory, it is never saved.
+    #[serde(skip)]
+    encryption: Option<Encryption>,
+    #[serde(skip)]
     output: Vec<SQLiteStatementResult>,
+    /// Counts the runs into the output, rows of a new run are never reused.
+    #[serde(skip)]
+    output_runs: usize,
     #[serde(skip)]
     embed: Vec<SQLiteStatementResult>,
     #[serde(skip)]
+    bytecode: Vec<SQLiteStatementResult>,
+    #[serde(skip)]
+    schema: Option<Vec<SchemaObject>>,
+    #[serde(skip)]
+    query_plan: Option<Vec<SQLiteQueryPlan>>,
+    #[serde(skip)]
     last_error: Option<FragileComfirmed<SQLightError>>,
     #[serde(skip)]
     import_progress: Option<ImportProgress>,
     #[serde(skip)]
     exported: Option<Exported>,
+    #[serde(skip)]
+    dropped_file: Option<FragileComfirmed<web_sys::File>>,
+}
+
+pub fn default_filename() -> String {
+    "test.db".into()
+}
+
+pub fn default_snapshot_limit() -> u32 {
+    64
 }
 
 impl Default for GlobalState {
@@ -67,21 +120,38 @@ impl Default for GlobalState {
             editor_config: EditorConfig::default(),
             orientation: Orientation::Automatic,
             theme: Theme::System,
-            keep_ctx: false,
+            run_mode: RunMode::DropContext,
             sql: DEFAULT_CODE.into(),
             run_selected_sql: false,
             multiple_ciphers: false,
+            continue_on_error: false,
+            show_schema: false,
+            filename: default_filename(),
+            attachments: vec![],
+            snapshot_limit: default_snapshot_limit(),
+            parameters: vec![],
+            csv_options: CsvOptions::default(),
+            script_name: None,
             editor: None,
             focus: None,
             is_focused: false,
             opened_focus: HashSet::new(),
             share_href: None,
             show_something: false,
+            running: false,
+            opens: 0,
+            pending_run: None,
+            encryption: None,
             output: vec![],
+            output_runs: 0,
             embed: vec![],
+            bytecode: vec![],
+            schema: None,
+            query_plan: None,
             last_error: None,
             import_progress: None,
             exported: None,
+            dropped_file: None,
         }
     }
 }
@@ -90,7 +160,20 @@ impl GlobalState {
     pub fn load() -> Option<Self> {
         let storage = window().local_storage().ok()??;
         let value = storage.get("config").ok()??;
-        serde_json::from_str(&value).ok()
+        let mut value = serde_json::from_str::<serde_json::Value>(&value).ok()?;
+        // `keep_ctx` was replaced by `run_mode`
+        if let Some(config) = value.as_object_mut()
+            && let Some(keep_ctx) = config.remove("keep_ctx")
+            && !config.contains_key("run_mode")
+        {
+            let mode = if keep_ctx.as_bool().unwrap_or_default() {
+                RunMode::KeepContext
+            } else {
+                RunMode::DropContext
+            };
+            config.insert("run_mode".into(), serde_json::to_value(mode).ok()?);
+        }
+        serde_json::from_value(value).ok()
     }
 
     pub fn save(&self) {
@@ -110,6 +193,17 @@ pub struct ImportProgress {
     pub loaded: f64,
     pub total: f64,
     pub opened: Option<bool>,
+    /// Bytes processed by the worker, only for files parsed by it.
+    pub processed: Option<f64>,
+    /// Only for files imported into a table.
+    pub imported: Option<ImportedTable>,
+}
+
+/// A run waiting for the parameter form to be submitted.
+pub struct PendingRun {
+    /// `None` if the run already started and is paused in the worker.
+    pub options: Option<RunOptions>,
+    pub names: Vec<String>,
 }
 
 pub struct Exported {
@@ -158,6 +252,8 @@ impl Vfs {
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Focus {
     Execute,
+    Plan,
+    Bytecode,
     Share,
     Status,
 }
@@ -4,10 +4,20 @@ use web_sys::MouseEvent;
 use crate::app::{button_menu_item::ButtonMenuItem, menu_aside::MenuAside, menu_group::MenuGroup};
 
 #[component]
-pub fn ToolsMenu<F, E, I>(on_format: F, on_embed: E, on_internal: I) -> impl IntoView
+pub fn ToolsMenu<F, E, P, C, B, I>(
+    on_format: F,
+    on_embed: E,
+    on_plan: P,
+    on_plan_at_cursor: C,
+    on_bytecode: B,
+    on_internal: I,
+) -> impl IntoView
 where
     F: Fn(MouseEvent) + Send + 'static,
     E: Fn(MouseEvent) + Send + 'static,
+    P: Fn(MouseEvent) + Send + 'static,
+    C: Fn(MouseEvent) + Send + 'static,
+    B: Fn(MouseEvent) + Send + 'static,
     I: Fn(MouseEvent) + Send + 'static,
 {
     view! {
@@ -18,6 +28,15 @@ where
             <ButtonMenuItem name="Embed Query Result".into() on_click=on_embed>
                 <MenuAside>"Embed results into query statements for easy sharing."</MenuAside>
             </ButtonMenuItem>
+            <ButtonMenuItem name="Explain Query Plan".into() on_click=on_plan>
+                <MenuAside>"Show the query plan of each statement as a tree."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Explain Query Plan at Cursor".into() on_click=on_plan_at_cursor>
+                <MenuAside>"Show the query plan of the statement under the cursor."</MenuAside>
+            </ButtonMenuItem>
+            <ButtonMenuItem name="Explain Bytecode".into() on_click=on_bytecode>
+                <MenuAside>"Show the VDBE bytecode of each statement without running it."</MenuAside>
+            </ButtonMenuItem>
             <a href="https://sqlite-internal.pages.dev" target="_blank">
                 <ButtonMenuItem name="SQLite internal".into() on_click=on_internal>
                     <MenuAside>
@@ -3,24 +3,39 @@ pub mod app;
 pub mod worker;
 
 use aceditor::EditorError;
-use app::{Exported, GlobalState, GlobalStateStoreFields, Vfs};
+use app::{Exported, GlobalState, GlobalStateStoreFields, PendingRun, Vfs};
 use fragile::Fragile;
-use js_sys::Uint8Array;
+use js_sys::{Int32Array, SharedArrayBuffer, Uint8Array};
 use leptos::prelude::*;
+use parking_lot::Mutex;
 use reactive_stores::Store;
 use std::{
+    collections::HashMap,
     ops::{Deref, DerefMut},
-    sync::{Arc, Once},
+    sync::{
+        Arc, Once,
+        atomic::{AtomicU32, Ordering},
+    },
 };
-use tokio::sync::{OnceCell, mpsc::UnboundedReceiver};
-use wasm_bindgen::{JsCast, prelude::Closure};
+use tokio::sync::{
+    OnceCell,
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
 use wasm_bindgen_futures::spawn_local;
-use web_sys::{MessageEvent, Worker, WorkerOptions, WorkerType};
+use web_sys::{ErrorEvent, Event, MessageEvent, Worker, WorkerOptions, WorkerType};
 
 use serde::{Deserialize, Serialize};
 
 type Result<T> = std::result::Result<T, WorkerError>;
 
+/// Serializer for messages between the window and the worker.
+///
+/// SQLite integers may exceed the safe integer range of a js number.
+const SERIALIZER: serde_wasm_bindgen::Serializer =
+    serde_wasm_bindgen::Serializer::new().serialize_large_number_types_as_bigints(true);
+
 /// A [`FragileComfirmed<T>`] wraps a non sendable `T` to be safely send to other threads.
 ///
 /// Once the value has been wrapped it can be sent to other threads but access
@@ -87,69 +102,492 @@ pub enum WorkerError {
     LoadDb(String),
     #[error("Failed to download db: {0}")]
     DownloadDb(String),
+    #[error("Failed to manage db: {0}")]
+    ManageDb(String),
+    #[error("Failed to attach db: {0}")]
+    AttachDb(String),
+    #[error("Failed to decrypt db: {0}")]
+    Encryption(String),
+    #[error("Failed to import csv: {0}")]
+    ImportCsv(String),
+    #[error("Failed to import json: {0}")]
+    ImportJson(String),
+    #[error("The running statement was interrupted")]
+    Interrupted,
+    #[error("The worker crashed and has been restarted")]
+    Crashed,
     #[error("Unexpected error")]
     Unexpected,
 }
 
+/// Identifies a request sent to the worker, it is echoed in the response.
+pub type RequestId = u32;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkerRequestMessage {
+    pub id: RequestId,
+    pub request: WorkerRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkerResponseMessage {
+    /// `None` if the response does not answer a request, e.g. [`WorkerResponse::Ready`].
+    pub id: Option<RequestId>,
+    pub response: WorkerResponse,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum WorkerRequest {
     Open(OpenOptions),
     Run(RunOptions),
     LoadDb(LoadDbOptions),
-    DownloadDb,
+    /// Import the rows of a csv file into a table of the main database.
+    ImportCsv(ImportCsvOptions),
+    /// Import the objects of a json array or ndjson document into a table of the main database.
+    ImportJson(ImportJsonOptions),
+    DownloadDb {
+        /// Gzipped by the worker.
+        compressed: bool,
+    },
+    /// Download the main database as sql statements.
+    DumpDb,
+    QueryPlan(ExplainOptions),
+    Schema,
+    Databases(DatabaseAction),
+    AttachDb(AttachDbOptions),
+    /// Detach the database with this alias.
+    DetachDb(String),
+    /// The parameters of the statements in the sql.
+    Parameters(String),
+    /// Resume a run paused by [`WorkerResponse::ParametersNeeded`], or stop it if `None`.
+    ///
+    /// Handled while the run is paused, there is no response.
+    ParameterValues(Option<Vec<SQLiteParameter>>),
+    Snapshots(SnapshotAction),
+    Encryption(EncryptionAction),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum WorkerResponse {
     Ready,
-    Open(Result<()>),
-    Run(Result<SQLiteRunResult>),
-    LoadDb(Result<()>),
+    /// The attachments that could be attached again.
+    Open(Result<Vec<Attachment>>),
+    RunStarted {
+        target: RunTarget,
+        /// Changes of the run will be rolled back.
+        dry_run: bool,
+    },
+    /// Rows of a statement, following chunks of the same statement append to it.
+    RowsChunk(SQLiteStatementTable),
+    StatementDone(SQLiteStatementTable),
+    StatementFailed {
+        position: [usize; 2],
+        error: SQLitendError,
+    },
+    /// The run is paused until [`WorkerRequest::ParameterValues`] for these names,
+    /// used by a statement that could not be prepared before the run.
+    ParametersNeeded(Vec<String>),
+    RunFinished(Result<()>),
+    /// The encryption the db was loaded with.
+    LoadDb(Result<Option<Encryption>>),
+    /// Bytes of the imported file processed so far.
+    ImportProgress {
+        processed: f64,
+    },
+    ImportCsv(Result<ImportedTable>),
+    ImportJson(Result<ImportedTable>),
     DownloadDb(Result<DownloadDbResponse>),
+    DumpDb(Result<DownloadDbResponse>),
+    QueryPlan(Result<Vec<SQLiteQueryPlan>>),
+    Schema(Result<Vec<SchemaObject>>),
+    /// The databases of the current VFS after the action.
+    Databases(Result<Vec<String>>),
+    AttachDb(Result<Attachment>),
+    /// The alias of the detached database.
+    DetachDb(Result<String>),
+    /// The distinct parameter names in order of appearance.
+    Parameters(Result<Vec<String>>),
+    /// The snapshots after the action, oldest first.
+    Snapshots(Result<Vec<SnapshotInfo>>),
+    Encryption(Result<EncryptionStatus>),
+}
+
+impl WorkerResponse {
+    /// Whether more responses to the same request will follow.
+    pub fn is_partial(&self) -> bool {
+        matches!(
+            self,
+            WorkerResponse::RunStarted { .. }
+                | WorkerResponse::RowsChunk(_)
+                | WorkerResponse::StatementDone(_)
+                | WorkerResponse::StatementFailed { .. }
+                | WorkerResponse::ParametersNeeded(_)
+                | WorkerResponse::ImportProgress { .. }
+        )
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DownloadDbResponse {
-    filename: String,
+    pub filename: String,
     #[serde(with = "serde_wasm_bindgen::preserve")]
-    data: Uint8Array,
+    pub data: Uint8Array,
+}
+
+/// Formats of compressed databases accepted by [`WorkerRequest::LoadDb`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compressed {
+    Gzip,
+    Zstd,
+    /// An archive with a single file.
+    Zip,
+}
+
+impl Compressed {
+    /// Detect the format by the magic bytes at the start of the data.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x1f, 0x8b, ..] => Some(Compressed::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compressed::Zstd),
+            [b'P', b'K', 3, 4, ..] => Some(Compressed::Zip),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compressed::Gzip => "gz",
+            Compressed::Zstd => "zst",
+            Compressed::Zip => "zip",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenOptions {
     pub filename: String,
     pub persist: bool,
+    /// An `Int32Array` over a `SharedArrayBuffer` polled while running statements,
+    /// `undefined` if the page is not cross-origin isolated.
+    #[serde(with = "serde_wasm_bindgen::preserve")]
+    pub interrupt: JsValue,
+    /// Attached after opening the main database.
+    pub attachments: Vec<Attachment>,
+    /// Applied to the main database right after opening it.
+    pub encryption: Option<Encryption>,
+}
+
+/// Ciphers of SQLite3MultipleCiphers.
+pub const CIPHERS: [&str; 7] = [
+    "chacha20",
+    "sqlcipher",
+    "aes256cbc",
+    "aes128cbc",
+    "rc4",
+    "ascon128",
+    "aegis",
+];
+
+/// The key and cipher of a SQLite3MultipleCiphers database.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Encryption {
+    /// e.g. `chacha20` or `sqlcipher`.
+    pub cipher: String,
+    /// The `legacy` parameter of the cipher, 0 for its current format.
+    pub legacy: u32,
+    pub key: String,
+}
+
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption")
+            .field("cipher", &self.cipher)
+            .field("legacy", &self.legacy)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum EncryptionAction {
+    Status,
+    /// Re-encrypt the main database, or decrypt it if `None`.
+    Rekey(Option<Encryption>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    /// The cipher used by the connection.
+    pub cipher: String,
+    pub encrypted: bool,
+}
+
+unsafe impl Send for OpenOptions {}
+unsafe impl Sync for OpenOptions {}
+
+/// Manage the databases of the current VFS.
+///
+/// Actions on the opened database close it, it is reopened by the next [`WorkerRequest::Open`].
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DatabaseAction {
+    List,
+    Rename { from: String, to: String },
+    Duplicate { from: String, to: String },
+    Delete(String),
+}
+
+/// Snapshots of the main database taken before each run.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SnapshotAction {
+    List,
+    /// Restore the database as it was before this run.
+    Revert(u32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    /// Runs are numbered from 1 since the database was opened.
+    pub run: u32,
+    pub sql: String,
+    pub size: usize,
+}
+
+/// A database of the VFS attached to the connection as a named schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub alias: String,
+    pub filename: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachDbOptions {
+    pub alias: String,
+    #[serde(with = "serde_wasm_bindgen::preserve")]
+    pub data: Uint8Array,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoadDbOptions {
     #[serde(with = "serde_wasm_bindgen::preserve")]
     pub data: Uint8Array,
+    /// Only for an encrypted db.
+    pub encryption: Option<Encryption>,
+}
+
+/// How the fields of a csv file are separated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CsvOptions {
+    /// `,` for csv, a tab for tsv.
+    pub delimiter: char,
+    /// The first row names the columns.
+    pub header: bool,
+    /// Fields are not quoted if `None`.
+    pub quote: Option<char>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            header: true,
+            quote: Some('"'),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportCsvOptions {
+    #[serde(with = "serde_wasm_bindgen::preserve")]
+    pub data: Uint8Array,
+    /// Created if it does not exist, otherwise the rows are appended to it.
+    pub table: String,
+    pub options: CsvOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportJsonOptions {
+    #[serde(with = "serde_wasm_bindgen::preserve")]
+    pub data: Uint8Array,
+    /// Created if it does not exist, otherwise the rows are appended to it.
+    pub table: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedTable {
+    pub table: String,
+    pub rows: u64,
+    /// The table did not exist before.
+    pub created: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RunOptions {
     pub sql: String,
-    pub embed: bool,
-    pub clear_on_prepare: bool,
+    pub target: RunTarget,
+    pub mode: RunMode,
+    /// Record a failed statement and keep running the following ones.
+    pub continue_on_error: bool,
+    /// Bound by name, missing parameters are NULL.
+    pub parameters: Vec<SQLiteParameter>,
+    /// Names already shown in the parameter form,
+    /// the run pauses with [`WorkerResponse::ParametersNeeded`] for the others.
+    pub asked: Vec<String>,
+    /// Bytes kept for snapshots of the db before each run, 0 disables them.
+    pub snapshot_limit: usize,
+}
+
+/// A value of the parameter form.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SQLiteParameter {
+    /// As written in the sql, e.g. `:name`, or `?1` for a bare `?`.
+    pub name: String,
+    pub kind: ParameterKind,
+    /// Parsed according to `kind`, a blob is written in hex.
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParameterKind {
+    Integer,
+    Real,
+    Text,
+    Blob,
+    Null,
+}
+
+impl ParameterKind {
+    pub const ALL: [ParameterKind; 5] = [
+        ParameterKind::Integer,
+        ParameterKind::Real,
+        ParameterKind::Text,
+        ParameterKind::Blob,
+        ParameterKind::Null,
+    ];
+
+    pub fn value(&self) -> &'static str {
+        match self {
+            ParameterKind::Integer => "Integer",
+            ParameterKind::Real => "Real",
+            ParameterKind::Text => "Text",
+            ParameterKind::Blob => "Blob",
+            ParameterKind::Null => "Null",
+        }
+    }
+
+    pub fn from_select(s: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.value() == s)
+            .unwrap_or(ParameterKind::Text)
+    }
+}
+
+/// What happens to the DB before and after a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunMode {
+    /// Each run starts with a new DB.
+    #[default]
+    DropContext,
+    /// Each run continues with the DB of the previous one.
+    KeepContext,
+    /// Run in a savepoint that is always rolled back.
+    DryRun,
+}
+
+impl RunMode {
+    pub fn value(&self) -> &'static str {
+        match self {
+            RunMode::DropContext => "Drop Context",
+            RunMode::KeepContext => "Keep Context",
+            RunMode::DryRun => "Dry Run",
+        }
+    }
+}
+
+/// Where the results of a run end up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunTarget {
+    /// The Execution tab.
+    Output,
+    /// Embedded into the statements in the editor.
+    Embed,
+    /// The Bytecode tab, statements are explained instead of executed.
+    Bytecode,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct InnerError {
-    pub code: i32,
-    pub message: String,
+pub struct ExplainOptions {
+    pub sql: String,
+    /// Only explain the statement at this byte offset of `sql`.
+    pub cursor: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SQLiteQueryPlan {
+    pub sql: String,
+    pub position: [usize; 2],
+    pub nodes: std::result::Result<Vec<QueryPlanNode>, SQLitendError>,
 }
 
+/// A row of `EXPLAIN QUERY PLAN`, nodes with `parent` 0 are at the top level.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SQLiteRunResult {
-    embed: bool,
-    result: Vec<SQLiteStatementResult>,
+pub struct QueryPlanNode {
+    pub id: i64,
+    pub parent: i64,
+    pub detail: String,
+}
+
+/// A table, view, index or trigger of `sqlite_schema`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaObject {
+    /// `main`, `temp` or the alias of an attachment.
+    pub schema: String,
+    /// `table`, `view`, `index` or `trigger`.
+    pub kind: String,
+    pub name: String,
+    /// The table or view an index or trigger belongs to.
+    pub table: String,
+    pub sql: Option<String>,
+    /// Only for tables and views.
+    pub columns: Vec<SchemaColumn>,
+    /// Only for tables.
+    pub foreign_keys: Vec<SchemaForeignKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaColumn {
+    pub name: String,
+    pub decl_type: String,
+    pub not_null: bool,
+    pub default: Option<String>,
+    pub primary_key: bool,
+    pub generated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaForeignKey {
+    pub from: String,
+    pub table: String,
+    /// `None` if it refers to the primary key.
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InnerError {
+    pub code: i32,
+    pub message: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SQLiteStatementResult {
-    Finish,
+    Finish {
+        /// The changes of a dry run were rolled back.
+        discarded: bool,
+    },
     Step(SQLiteStatementTable),
+    Error {
+        position: [usize; 2],
+        error: SQLitendError,
+    },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -157,12 +595,64 @@ pub struct SQLiteStatementTable {
     pub sql: String,
     pub position: [usize; 2],
     pub values: Option<SQLiteStatementValues>,
+    /// Only known once the statement is done.
+    pub metrics: Option<SQLiteStatementMetrics>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SQLiteStatementMetrics {
+    /// Execution time in milliseconds.
+    pub duration: f64,
+    /// Rows inserted, updated or deleted by the statement.
+    pub changes: i64,
+    /// The rowid of the last row inserted by the statement.
+    pub last_insert_rowid: Option<i64>,
+    pub full_scan_steps: i32,
+    pub sorts: i32,
+    pub autoindexes: i32,
+    pub vm_steps: i32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SQLiteStatementValues {
     pub columns: Vec<String>,
-    pub rows: Vec<Vec<String>>,
+    pub rows: Vec<Vec<SQLiteValue>>,
+}
+
+/// A value with its SQLite storage class.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SQLiteValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(#[serde(with = "serde_bytes")] Vec<u8>),
+}
+
+/// Formats the value as a SQL literal.
+impl std::fmt::Display for SQLiteValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SQLiteValue::Null => f.write_str("NULL"),
+            SQLiteValue::Integer(number) => write!(f, "{number}"),
+            // the same as sqlite3 shell
+            SQLiteValue::Real(number) if number.is_infinite() => {
+                f.write_str(if number.is_sign_positive() {
+                    "9e999"
+                } else {
+                    "-9e999"
+                })
+            }
+            SQLiteValue::Real(number) => write!(f, "{number:?}"),
+            SQLiteValue::Text(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            SQLiteValue::Blob(blob) => write!(f, "x'{}'", hex::encode(blob)),
+        }
+    }
+}
+
+/// Quotes a name as a SQL identifier.
+pub fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
 }
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
@@ -175,6 +665,10 @@ pub enum SQLitendError {
     Prepare(InnerError),
     #[error("An error occurred while stepping to the next line: {0:#?}")]
     Step(InnerError),
+    #[error("An error occurred while explaining stmt: {0:#?}")]
+    Explain(InnerError),
+    #[error("An error occurred while binding parameter {0}")]
+    Bind(String),
     #[error("An error occurred while getting column name: {0}")]
     GetColumnName(String),
     #[error("The text is not a utf8 string")]
@@ -183,15 +677,120 @@ pub enum SQLitendError {
     UnsupportColumnType(i32),
 }
 
-pub struct WorkerHandle(Worker);
+type PendingRequests = Arc<Mutex<HashMap<RequestId, oneshot::Sender<WorkerResponse>>>>;
+
+pub struct WorkerHandle {
+    /// Replaced by a fresh worker if it crashes.
+    worker: Mutex<Worker>,
+    uri: &'static str,
+    next_id: AtomicU32,
+    pending: PendingRequests,
+    responses: UnboundedSender<WorkerResponse>,
+    interrupt: Option<Int32Array>,
+}
 
 impl WorkerHandle {
-    pub fn send_task(&self, req: WorkerRequest) {
+    fn post(&self, id: RequestId, request: WorkerRequest) -> bool {
+        let message = WorkerRequestMessage { id, request };
         if let Err(err) = self
-            .0
-            .post_message(&serde_wasm_bindgen::to_value(&req).unwrap())
+            .worker
+            .lock()
+            .post_message(&message.serialize(&SERIALIZER).unwrap())
         {
-            log::error!("Failed to send task to worker: {req:?}, {err:?}");
+            log::error!("Failed to send task to worker: {message:?}, {err:?}");
+            return false;
+        }
+        true
+    }
+
+    fn next_id(&self) -> RequestId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Send a request, the response is applied to the global state.
+    pub fn send_task(&self, req: WorkerRequest) -> RequestId {
+        let id = self.next_id();
+        self.post(id, req);
+        id
+    }
+
+    /// Send a request and wait for the final response with the same id.
+    ///
+    /// The final response is handed to the caller instead of the global state,
+    /// unless the returned future is dropped before the response arrives.
+    /// Partial responses are always applied to the global state.
+    pub fn request(&self, req: WorkerRequest) -> impl Future<Output = Result<WorkerResponse>> {
+        let id = self.next_id();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().insert(id, tx);
+        let posted = self.post(id, req);
+        if !posted {
+            self.pending.lock().remove(&id);
+        }
+        async move {
+            if !posted {
+                return Err(WorkerError::Unexpected);
+            }
+            // the waiter is dropped if the worker crashes
+            rx.await.map_err(|_| WorkerError::Crashed)
+        }
+    }
+
+    /// Ask the worker to interrupt the running statement.
+    ///
+    /// Returns `false` if there is no shared memory to reach a busy worker.
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+            .as_ref()
+            .is_some_and(|flag| js_sys::Atomics::store(flag, 0, 1).is_ok())
+    }
+
+    /// Stop the worker and start a fresh one, `reason` is reported as the last error.
+    fn restart(&'static self, state: Store<GlobalState>, reason: WorkerError) {
+        // the same crash may be reported more than once
+        let worker = self.worker.lock();
+        worker.set_onerror(None);
+        worker.set_onmessageerror(None);
+        worker.terminate();
+        drop(worker);
+        spawn_local(self.respawn(state, reason));
+    }
+
+    /// Respawn the worker once it reports an `error` or `messageerror` event.
+    fn watch(&'static self, state: Store<GlobalState>) {
+        let on_error = Closure::<dyn Fn(Event)>::new(move |ev: Event| {
+            match ev.dyn_ref::<ErrorEvent>() {
+                Some(ev) => log::error!("Worker crashed: {}", ev.message()),
+                None => log::error!("Worker crashed: {}", ev.type_()),
+            }
+            self.restart(state, WorkerError::Crashed);
+        });
+
+        let worker = self.worker.lock();
+        worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        worker.set_onmessageerror(Some(on_error.as_ref().unchecked_ref()));
+        on_error.forget();
+    }
+
+    async fn respawn(&'static self, state: Store<GlobalState>, reason: WorkerError) {
+        // callers waiting for a response get `WorkerError::Crashed`
+        self.pending.lock().clear();
+        state.running().set(false);
+        state
+            .last_error()
+            .set(Some(SQLightError::new_worker(reason)));
+
+        let worker =
+            spawn_worker(self.uri, self.responses.clone(), Arc::clone(&self.pending)).await;
+        *self.worker.lock() = worker;
+        self.watch(state);
+
+        // not sent with `send_task`, its response would clear the reported error
+        let options = untrack(|| open_options(state, self));
+        match self.request(WorkerRequest::Open(options)).await {
+            Ok(WorkerResponse::Open(result)) => handle_open(state, result),
+            Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
+            Ok(resp) => log::error!("Unexpected response to open request: {resp:?}"),
         }
     }
 }
@@ -199,17 +798,77 @@ impl WorkerHandle {
 unsafe impl Send for WorkerHandle {}
 unsafe impl Sync for WorkerHandle {}
 
+async fn worker(state: Store<GlobalState>) -> &'static WorkerHandle {
+    if state.multiple_ciphers().get_untracked() {
+        sqlite3mc(state).await
+    } else {
+        sqlite3(state).await
+    }
+}
+
 fn send_request(state: Store<GlobalState>, req: WorkerRequest) {
     spawn_local(async move {
-        if state.multiple_ciphers().get_untracked() {
-            sqlite3mc(state).await
-        } else {
-            sqlite3(state).await
+        worker(state).await.send_task(req);
+    });
+}
+
+fn interrupt_run(state: Store<GlobalState>) {
+    // a run paused for parameters is not busy
+    if let Some(PendingRun { options: None, .. }) = state.pending_run().write().take() {
+        send_request(state, WorkerRequest::ParameterValues(None));
+        return;
+    }
+    spawn_local(async move {
+        let worker = worker(state).await;
+        if worker.interrupt() {
+            return;
+        }
+        // a busy worker can only be reached through shared memory, which requires
+        // the page to be cross-origin isolated, otherwise it is restarted
+        if state.vfs().get_untracked() == Vfs::Memory
+            && !window()
+                .confirm_with_message(
+                    "Stopping restarts the worker, changes to the memory database will be lost. Stop?",
+                )
+                .unwrap_or_default()
+        {
+            return;
         }
-        .send_task(req);
+        worker.restart(state, WorkerError::Interrupted);
     });
 }
 
+/// Reload the schema shown in the schema panel.
+///
+/// Errors are only logged, they should not replace the error of the last run.
+fn handle_open(state: Store<GlobalState>, result: Result<Vec<Attachment>>) {
+    *state.opens().write() += 1;
+    match result {
+        Ok(attachments) => {
+            state.attachments().set(attachments);
+            refresh_schema(state);
+        }
+        Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
+    }
+}
+
+fn refresh_schema(state: Store<GlobalState>) {
+    spawn_local(async move {
+        match request(state, WorkerRequest::Schema).await {
+            Ok(WorkerResponse::Schema(Ok(schema))) => state.schema().set(Some(schema)),
+            Ok(WorkerResponse::Schema(Err(err))) | Err(err) => {
+                log::error!("Failed to load schema: {err}");
+            }
+            Ok(resp) => log::error!("Unexpected response to schema request: {resp:?}"),
+        }
+    });
+}
+
+/// Send a request to the current worker and wait for its response.
+pub async fn request(state: Store<GlobalState>, req: WorkerRequest) -> Result<WorkerResponse> {
+    worker(state).await.request(req).await
+}
+
 async fn sqlite3mc(state: Store<GlobalState>) -> &'static WorkerHandle {
     static ONCE: Once = Once::new();
     static WORKER: OnceCell<WorkerHandle> = OnceCell::const_new();
@@ -219,6 +878,7 @@ async fn sqlite3mc(state: Store<GlobalState>) -> &'static WorkerHandle {
         .await;
 
     ONCE.call_once(|| {
+        worker.watch(state);
         connect_db(state, worker);
         Effect::new(move || connect_db(state, worker));
     });
@@ -234,6 +894,7 @@ async fn sqlite3(state: Store<GlobalState>) -> &'static WorkerHandle {
         .await;
 
     ONCE.call_once(|| {
+        worker.watch(state);
         connect_db(state, worker);
         Effect::new(move || connect_db(state, worker));
     });
@@ -241,13 +902,55 @@ async fn sqlite3(state: Store<GlobalState>) -> &'static WorkerHandle {
 }
 
 fn connect_db(state: Store<GlobalState>, handle: &'static WorkerHandle) {
-    handle.send_task(crate::WorkerRequest::Open(crate::OpenOptions {
-        filename: "test.db".into(),
+    handle.send_task(WorkerRequest::Open(open_options(state, handle)));
+}
+
+fn open_options(state: Store<GlobalState>, handle: &WorkerHandle) -> OpenOptions {
+    OpenOptions {
+        filename: state.filename().get(),
         persist: *state.vfs().read() == Vfs::OPFS,
-    }));
+        interrupt: handle
+            .interrupt
+            .clone()
+            .map_or(JsValue::UNDEFINED, JsValue::from),
+        // the worker already attached new ones, no need to reopen
+        attachments: state.attachments().get_untracked(),
+        encryption: state.encryption().get(),
+    }
+}
+
+/// A shared flag is only available if the page is cross-origin isolated.
+fn interrupt_flag() -> Option<Int32Array> {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from("crossOriginIsolated"))
+        .ok()?
+        .as_bool()?
+        .then(|| Int32Array::new(&SharedArrayBuffer::new(4)))
+}
+
+async fn setup_worker(state: Store<GlobalState>, uri: &'static str) -> WorkerHandle {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let pending = PendingRequests::default();
+
+    spawn_local(handle_state(state, rx));
+
+    let worker = spawn_worker(uri, tx.clone(), Arc::clone(&pending)).await;
+
+    WorkerHandle {
+        worker: Mutex::new(worker),
+        uri,
+        next_id: AtomicU32::new(1),
+        pending,
+        responses: tx,
+        interrupt: interrupt_flag(),
+    }
 }
 
-async fn setup_worker(state: Store<GlobalState>, uri: &str) -> WorkerHandle {
+/// Start a worker and wait until it is ready.
+async fn spawn_worker(
+    uri: &str,
+    tx: UnboundedSender<WorkerResponse>,
+    waiting: PendingRequests,
+) -> Worker {
     let opts = WorkerOptions::new();
     opts.set_type(WorkerType::Module);
 
@@ -259,59 +962,215 @@ async fn setup_worker(state: Store<GlobalState>, uri: &str) -> WorkerHandle {
     let notify = Arc::new(tokio::sync::Notify::new());
     let wait = Arc::clone(&notify);
 
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-
     let on_message = Closure::<dyn Fn(MessageEvent)>::new(move |ev: MessageEvent| {
         match serde_wasm_bindgen::from_value(ev.data()) {
-            Ok(WorkerResponse::Ready) => notify.notify_one(),
-            Ok(resp) => tx.send(resp).unwrap(),
+            Ok(WorkerResponseMessage {
+                response: WorkerResponse::Ready,
+                ..
+            }) => notify.notify_one(),
+            Ok(WorkerResponseMessage { id, response }) => {
+                let waiter = id
+                    .filter(|_| !response.is_partial())
+                    .and_then(|id| waiting.lock().remove(&id));
+                // Fall back to the global state if nobody is waiting anymore
+                let response = match waiter {
+                    Some(waiter) => match waiter.send(response) {
+                        Ok(()) => return,
+                        Err(response) => response,
+                    },
+                    None => response,
+                };
+                tx.send(response).unwrap();
+            }
             Err(err) => log::error!("Failed to parse message {err:?}"),
         }
     });
 
-    spawn_local(handle_state(state, rx));
-
     worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
     on_message.forget();
     wait.notified().await;
 
-    WorkerHandle(worker)
+    worker
+}
+
+/// Append a streamed statement to the output, merging rows of the same statement.
+fn merge_statement(output: &mut Vec<SQLiteStatementResult>, table: SQLiteStatementTable) {
+    if let Some(SQLiteStatementResult::Step(last)) = output.last_mut()
+        && last.position == table.position
+    {
+        if table.metrics.is_some() {
+            last.metrics = table.metrics;
+        }
+        match (&mut last.values, table.values) {
+            (Some(values), Some(chunk)) => values.rows.extend(chunk.rows),
+            (values @ None, chunk) => *values = chunk,
+            (Some(_), None) => (),
+        }
+        return;
+    }
+    output.push(SQLiteStatementResult::Step(table));
 }
 
 async fn handle_state(state: Store<GlobalState>, mut rx: UnboundedReceiver<WorkerResponse>) {
+    let output_of = |target| match target {
+        RunTarget::Output => state.output(),
+        RunTarget::Embed => state.embed(),
+        RunTarget::Bytecode => state.bytecode(),
+    };
+    // where the rows of the current run go
+    let mut target = RunTarget::Output;
+    let mut discarded = false;
+
     while let Some(resp) = rx.recv().await {
-        state.last_error().set(None);
+        let output = output_of(target);
+
+        // errors reported while streaming must outlive the following chunks
+        if !resp.is_partial() || matches!(resp, WorkerResponse::RunStarted { .. }) {
+            state.last_error().set(None);
+        }
 
         match resp {
             WorkerResponse::Ready => unreachable!(),
-            WorkerResponse::Open(result) => {
-                if let Err(err) = result {
-                    state.last_error().set(Some(SQLightError::new_worker(err)));
+            WorkerResponse::Open(result) => handle_open(state, result),
+            WorkerResponse::RunStarted {
+                target: run_target,
+                dry_run,
+            } => {
+                target = run_target;
+                discarded = dry_run;
+                output_of(target).write().clear();
+                if target == RunTarget::Output {
+                    *state.output_runs().write() += 1;
+                }
+            }
+            WorkerResponse::RowsChunk(table) | WorkerResponse::StatementDone(table) => {
+                merge_statement(&mut output.write(), table);
+            }
+            WorkerResponse::StatementFailed { position, error } => {
+                output
+                    .write()
+                    .push(SQLiteStatementResult::Error { position, error });
+            }
+            WorkerResponse::ParametersNeeded(names) => {
+                state.pending_run().set(Some(PendingRun {
+                    options: None,
+                    names,
+                }));
+            }
+            WorkerResponse::RunFinished(result) => {
+                state.running().set(false);
+                // the form of a paused run is of no use anymore
+                state.pending_run().maybe_update(|pending| match pending {
+                    Some(PendingRun { options: None, .. }) => pending.take().is_some(),
+                    _ => false,
+                });
+                refresh_schema(state);
+                match result {
+                    Ok(()) => output
+                        .write()
+                        .push(SQLiteStatementResult::Finish { discarded }),
+                    Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
+                }
+            }
+            WorkerResponse::LoadDb(result) => {
+                let opened = result.is_ok();
+                if let Some(progress) = &mut *state.import_progress().write() {
+                    progress.opened = Some(opened);
+                }
+                match result {
+                    // the worker already opened the db with it
+                    Ok(encryption) => *state.encryption().write_untracked() = encryption,
+                    Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
+                }
+                // the loaded db must not be dropped by the next run, a failed load keeps the old db
+                if opened && state.run_mode().get_untracked() == RunMode::DropContext {
+                    state.run_mode().set(RunMode::KeepContext);
+                }
+                refresh_schema(state);
+            }
+            WorkerResponse::ImportProgress { processed } => {
+                if let Some(progress) = &mut *state.import_progress().write() {
+                    progress.processed = Some(processed);
+                }
+            }
+            WorkerResponse::ImportCsv(result) | WorkerResponse::ImportJson(result) => {
+                let imported = result.is_ok();
+                if let Some(progress) = &mut *state.import_progress().write() {
+                    progress.opened = Some(imported);
+                }
+                match result {
+                    Ok(table) => {
+                        if let Some(progress) = &mut *state.import_progress().write() {
+                            progress.imported = Some(table);
+                        }
+                    }
+                    Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
+                }
+                // the imported rows must not be dropped by the next run
+                if imported {
+                    state.run_mode().update(|mode| {
+                        if *mode == RunMode::DropContext {
+                            *mode = RunMode::KeepContext;
+                        }
+                    });
                 }
+                refresh_schema(state);
             }
-            WorkerResponse::Run(result) => match result {
-                Ok(SQLiteRunResult { embed, result }) => {
-                    if embed {
-                        state.embed().set(result);
-                    } else {
-                        state.output().set(result);
+            WorkerResponse::AttachDb(result) => {
+                if let Some(progress) = &mut *state.import_progress().write() {
+                    progress.opened = Some(result.is_ok());
+                }
+                match result {
+                    Ok(attachment) => {
+                        state.attachments().write().push(attachment);
+                        refresh_schema(state);
                     }
+                    Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
+                }
+            }
+            WorkerResponse::DetachDb(result) => match result {
+                Ok(alias) => {
+                    state
+                        .attachments()
+                        .write()
+                        .retain(|attachment| attachment.alias != alias);
+                    refresh_schema(state);
                 }
                 Err(err) => state.last_error().set(Some(SQLightError::new_worker(err))),
             },
-            WorkerResponse::LoadDb(result) => {
-                let keep_ctx = result.is_ok();
-                if let Some(progress) = &mut *state.import_progress().write() {
-                    progress.opened = Some(keep_ctx);
+            WorkerResponse::Databases(result) => {
+                if let Err(err) = result {
+                    state.last_error().set(Some(SQLightError::new_worker(err)));
                 }
+            }
+            WorkerResponse::Parameters(result) => {
+                if let Err(err) = result {
+                    state.last_error().set(Some(SQLightError::new_worker(err)));
+                }
+            }
+            WorkerResponse::Snapshots(result) => {
                 if let Err(err) = result {
                     state.last_error().set(Some(SQLightError::new_worker(err)));
                 }
-                state
-                    .keep_ctx()
-                    .maybe_update(|keep| std::mem::replace(keep, keep_ctx) != keep_ctx);
             }
-            WorkerResponse::DownloadDb(result) => match result {
+            WorkerResponse::Encryption(result) => {
+                if let Err(err) = result {
+                    state.last_error().set(Some(SQLightError::new_worker(err)));
+                }
+            }
+            WorkerResponse::Schema(result) => match result {
+                Ok(schema) => state.schema().set(Some(schema)),
+                Err(err) => log::error!("Failed to load schema: {err}"),
+            },
+            WorkerResponse::QueryPlan(result) => match result {
+                Ok(plans) => {
+                    state.query_plan().set(Some(plans));
+                }
+                Err(err) => {
+                    state.last_error().set(Some(SQLightError::new_worker(err)));
+                }
+            },
+            WorkerResponse::DownloadDb(result) | WorkerResponse::DumpDb(result) => match result {
                 Ok(resp) => {
                     state.exported().set(Some(Exported {
                         filename: resp.filename,
@@ -0,0 +1,61 @@
+use std::io::{Cursor, Read, Write};
+
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+use ruzstd::decoding::StreamingDecoder;
+
+use crate::Compressed;
+
+type Result<T> = std::result::Result<T, String>;
+
+fn zstd(data: &[u8]) -> Result<Vec<u8>> {
+    let mut input = data;
+    let mut output = vec![];
+    // a file may hold several frames, e.g. written by pzstd
+    while !input.is_empty() {
+        let mut decoder = StreamingDecoder::new(&mut input).map_err(|err| format!("{err}"))?;
+        decoder
+            .read_to_end(&mut output)
+            .map_err(|err| format!("{err}"))?;
+    }
+    Ok(output)
+}
+
+fn zip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|err| format!("{err}"))?;
+    let files = (0..archive.len())
+        .filter(|&idx| archive.by_index_raw(idx).is_ok_and(|file| file.is_file()))
+        .collect::<Vec<_>>();
+    let [idx] = files[..] else {
+        return Err(format!("zip has {} files, expected one", files.len()));
+    };
+    let mut file = archive.by_index(idx).map_err(|err| format!("{err}"))?;
+    let mut output = Vec::with_capacity(usize::try_from(file.size()).unwrap_or_default());
+    file.read_to_end(&mut output)
+        .map_err(|err| format!("{err}"))?;
+    Ok(output)
+}
+
+/// Decompress the data if it starts with the magic bytes of a known format.
+pub fn decompress(data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(compressed) = Compressed::detect(&data) else {
+        return Ok(data);
+    };
+    let result = match compressed {
+        Compressed::Gzip => {
+            let mut output = vec![];
+            MultiGzDecoder::new(data.as_slice())
+                .read_to_end(&mut output)
+                .map(|_| output)
+                .map_err(|err| format!("{err}"))
+        }
+        Compressed::Zstd => zstd(&data),
+        Compressed::Zip => zip(&data),
+    };
+    result.map_err(|err| format!("invalid {} file: {err}", compressed.extension()))
+}
+
+pub fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).map_err(|err| format!("{err}"))?;
+    encoder.finish().map_err(|err| format!("{err}"))
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use crate::{SQLiteValue, WorkerError, identifier};
+
+use super::sqlitend::SQLiteDb;
+
+type Result<T> = std::result::Result<T, WorkerError>;
+
+fn text(value: SQLiteValue) -> Option<String> {
+    match value {
+        SQLiteValue::Text(text) => Some(text),
+        _ => None,
+    }
+}
+
+/// A table of `sqlite_schema`.
+struct Table {
+    name: String,
+    sql: String,
+}
+
+/// Tables referenced by foreign keys come before the tables referencing them.
+///
+/// Tables in a cycle keep the order of `sqlite_schema`.
+fn dependency_order(db: &Arc<SQLiteDb>, tables: Vec<Table>) -> Result<Vec<Table>> {
+    let mut parents = Vec::with_capacity(tables.len());
+    for table in &tables {
+        let sql = format!(
+            "SELECT DISTINCT \"table\" FROM pragma_foreign_key_list({})",
+            SQLiteValue::Text(table.name.clone())
+        );
+        let names = db
+            .query(&sql)?
+            .into_iter()
+            .filter_map(|row| row.into_iter().next().and_then(text))
+            // references to itself do not need an order
+            .filter(|parent| !parent.eq_ignore_ascii_case(&table.name))
+            .collect::<Vec<_>>();
+        parents.push(names);
+    }
+
+    let mut pending = tables.into_iter().zip(parents).collect::<Vec<_>>();
+    let mut ordered = Vec::<Table>::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|(_, parents)| {
+            parents.iter().all(|parent| {
+                // a parent that is not a table of the dump is not waited for
+                ordered
+                    .iter()
+                    .any(|table| table.name.eq_ignore_ascii_case(parent))
+                    || !pending
+                        .iter()
+                        .any(|(table, _)| table.name.eq_ignore_ascii_case(parent))
+            })
+        });
+        let (table, _) = pending.remove(ready.unwrap_or(0));
+        ordered.push(table);
+    }
+    Ok(ordered)
+}
+
+/// Append an `INSERT` for each row of the table.
+fn dump_rows(db: &Arc<SQLiteDb>, table: &str, dump: &mut String) -> Result<()> {
+    // generated columns can not be inserted
+    let columns = db
+        .query(&format!(
+            "SELECT name, hidden FROM pragma_table_xinfo({})",
+            SQLiteValue::Text(table.into())
+        ))?
+        .into_iter()
+        .filter_map(|row| match <[SQLiteValue; 2]>::try_from(row) {
+            Ok([SQLiteValue::Text(name), SQLiteValue::Integer(hidden)]) => Some((name, hidden)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let inserted = columns
+        .iter()
+        .filter(|(_, hidden)| *hidden == 0)
+        .map(|(name, _)| identifier(name))
+        .collect::<Vec<_>>();
+    if inserted.is_empty() {
+        return Ok(());
+    }
+
+    let (select, into) = if inserted.len() == columns.len() {
+        ("*".to_string(), identifier(table))
+    } else {
+        let list = inserted.join(",");
+        (list.clone(), format!("{}({list})", identifier(table)))
+    };
+
+    let Some(stmt) = db
+        .prepare(&format!("SELECT {select} FROM {}", identifier(table)))?
+        .prepare_next()?
+    else {
+        return Ok(());
+    };
+    while let Some(values) = stmt.get_one()? {
+        for row in values.rows {
+            let row = row.iter().map(ToString::to_string).collect::<Vec<_>>();
+            *dump += &format!("INSERT INTO {into} VALUES({});\n", row.join(","));
+        }
+    }
+    Ok(())
+}
+
+/// Dump the main database as sql, the same way as `.dump` of the sqlite3 shell.
+///
+/// Blobs are written as hex literals.
+pub fn dump_db(db: &Arc<SQLiteDb>) -> Result<String> {
+    let mut dump = String::new();
+    let user_version = match db.query("PRAGMA user_version")?.first().map(Vec::as_slice) {
+        Some([SQLiteValue::Integer(version)]) => *version,
+        _ => 0,
+    };
+    dump += "PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n";
+    dump += &format!("PRAGMA user_version={user_version};\n");
+
+    let tables = db
+        .query(
+            "SELECT name, sql FROM sqlite_schema \
+             WHERE sql NOT NULL AND type = 'table' ORDER BY rowid",
+        )?
+        .into_iter()
+        .filter_map(|row| {
+            let mut row = row.into_iter().map(text);
+            Some(Table {
+                name: row.next()??,
+                sql: row.next()??,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut writable_schema = false;
+    let mut internal = vec![];
+    let mut user_tables = vec![];
+    for table in tables {
+        if table.name.starts_with("sqlite_") {
+            internal.push(table);
+        } else {
+            user_tables.push(table);
+        }
+    }
+
+    for table in dependency_order(db, user_tables)? {
+        if table.sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE") {
+            // created from the schema as is, its content is in the shadow tables
+            if !writable_schema {
+                dump += "PRAGMA writable_schema=ON;\n";
+                writable_schema = true;
+            }
+            let name = SQLiteValue::Text(table.name.clone());
+            let sql = SQLiteValue::Text(table.sql.clone());
+            dump += &format!(
+                "INSERT INTO sqlite_schema(type,name,tbl_name,rootpage,sql) \
+                 VALUES('table',{name},{name},0,{sql});\n"
+            );
+            continue;
+        }
+        dump += &format!("{};\n", table.sql);
+        dump_rows(db, &table.name, &mut dump)?;
+    }
+
+    for table in internal {
+        match table.name.as_str() {
+            "sqlite_sequence" => {
+                dump += "DELETE FROM sqlite_sequence;\n";
+                dump_rows(db, &table.name, &mut dump)?;
+            }
+            "sqlite_stat1" => {
+                dump += "ANALYZE sqlite_schema;\n";
+                dump_rows(db, &table.name, &mut dump)?;
+            }
+            _ => (),
+        }
+    }
+
+    let others = db.query(
+        "SELECT sql FROM sqlite_schema \
+         WHERE sql NOT NULL AND type IN ('index', 'trigger', 'view') ORDER BY rowid",
+    )?;
+    for sql in others
+        .into_iter()
+        .filter_map(|row| row.into_iter().next().and_then(text))
+    {
+        dump += &format!("{sql};\n");
+    }
+
+    if writable_schema {
+        dump += "PRAGMA writable_schema=OFF;\n";
+    }
+    dump += "COMMIT;\n";
+    Ok(dump)
+}
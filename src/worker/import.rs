@@ -0,0 +1,534 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Value, value::RawValue};
+
+use crate::{CsvOptions, ImportedTable, SQLiteValue, WorkerError, WorkerResponse, identifier};
+
+use super::sqlitend::{SQLiteDb, SQLitePreparedStatement};
+
+type Result<T> = std::result::Result<T, WorkerError>;
+
+/// Rows used to infer the affinity of the columns.
+const SAMPLE_ROWS: usize = 1000;
+/// The progress is posted every this many rows.
+const PROGRESS_ROWS: u64 = 10_000;
+
+/// The type of a new column, each one can hold the values of the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Affinity {
+    Integer,
+    Real,
+    Text,
+}
+
+impl Affinity {
+    /// `None` for an empty field, it fits any column.
+    fn of(field: &str) -> Option<Self> {
+        let digits = field.strip_prefix(['-', '+']).unwrap_or(field);
+        // e.g. zip codes or ids, the zeros and spaces would be lost as numbers
+        let leading_zero = digits.len() > 1
+            && digits.starts_with('0')
+            && digits[1..].starts_with(|c: char| c.is_ascii_digit());
+        if field.trim().is_empty() {
+            None
+        } else if leading_zero || field.trim() != field {
+            Some(Affinity::Text)
+        } else if field.parse::<i64>().is_ok() {
+            Some(Affinity::Integer)
+        } else if field.parse::<f64>().is_ok_and(f64::is_finite) {
+            Some(Affinity::Real)
+        } else {
+            Some(Affinity::Text)
+        }
+    }
+
+    /// `None` for NULL, it fits any column.
+    fn of_value(value: &SQLiteValue) -> Option<Self> {
+        match value {
+            SQLiteValue::Null => None,
+            SQLiteValue::Integer(_) => Some(Affinity::Integer),
+            SQLiteValue::Real(_) => Some(Affinity::Real),
+            SQLiteValue::Text(_) | SQLiteValue::Blob(_) => Some(Affinity::Text),
+        }
+    }
+
+    fn decl_type(self) -> &'static str {
+        match self {
+            Affinity::Integer => "INTEGER",
+            Affinity::Real => "REAL",
+            Affinity::Text => "TEXT",
+        }
+    }
+
+    /// Fields that do not fit are kept as text, empty numbers are NULL.
+    fn value(self, field: &str) -> SQLiteValue {
+        let text = || SQLiteValue::Text(field.to_string());
+        match (self, Affinity::of(field)) {
+            (Affinity::Text, _) => text(),
+            (_, None) => SQLiteValue::Null,
+            (Affinity::Integer, Some(Affinity::Integer)) => {
+                field.parse().map_or_else(|_| text(), SQLiteValue::Integer)
+            }
+            (Affinity::Real, Some(Affinity::Integer | Affinity::Real)) => {
+                field.parse().map_or_else(|_| text(), SQLiteValue::Real)
+            }
+            _ => text(),
+        }
+    }
+}
+
+/// Inserts rows into a table inside a transaction.
+struct TableWriter {
+    db: Arc<SQLiteDb>,
+    insert: SQLitePreparedStatement,
+    table: String,
+    created: bool,
+    rows: u64,
+}
+
+impl TableWriter {
+    /// Create the table with these columns and types, or append to it if it exists.
+    ///
+    /// Without names the values are appended in the order of the columns of the table.
+    fn begin(
+        db: &Arc<SQLiteDb>,
+        table: &str,
+        names: Option<&[String]>,
+        decl_types: &[&str],
+    ) -> Result<Self> {
+        let exists = !db
+            .query(&format!(
+                "SELECT 1 FROM sqlite_schema WHERE type = 'table' AND name = {} COLLATE NOCASE",
+                SQLiteValue::Text(table.into())
+            ))?
+            .is_empty();
+
+        let columns = match names {
+            Some(names) => names.to_vec(),
+            None => (1..=decl_types.len())
+                .map(|idx| format!("column{idx}"))
+                .collect(),
+        };
+
+        db.query("BEGIN")?;
+        let writer = (|| {
+            if !exists {
+                let definitions = columns
+                    .iter()
+                    .zip(decl_types)
+                    .map(|(name, decl_type)| format!("{} {decl_type}", identifier(name)))
+                    .collect::<Vec<_>>();
+                db.query(&format!(
+                    "CREATE TABLE {} ({})",
+                    identifier(table),
+                    definitions.join(", ")
+                ))?;
+            }
+
+            let column_list = match names {
+                Some(names) if exists => {
+                    let names = names.iter().map(|name| identifier(name));
+                    format!(" ({})", names.collect::<Vec<_>>().join(", "))
+                }
+                _ => String::new(),
+            };
+            let placeholders = vec!["?"; decl_types.len()].join(", ");
+            let sql = format!(
+                "INSERT INTO {}{column_list} VALUES ({placeholders})",
+                identifier(table)
+            );
+            let insert = db
+                .prepare(&sql)?
+                .prepare_next()?
+                .ok_or(WorkerError::Unexpected)?;
+
+            Ok(TableWriter {
+                db: Arc::clone(db),
+                insert,
+                table: table.to_string(),
+                created: !exists,
+                rows: 0,
+            })
+        })();
+        if writer.is_err() {
+            let _ = db.query("ROLLBACK");
+        }
+        writer
+    }
+
+    fn insert(&mut self, row: &[SQLiteValue]) -> Result<()> {
+        self.insert.bind_values(row)?;
+        self.insert.execute()?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn commit(self) -> Result<ImportedTable> {
+        let TableWriter {
+            db,
+            insert,
+            table,
+            created,
+            rows,
+        } = self;
+        drop(insert);
+        db.query("COMMIT")?;
+        Ok(ImportedTable {
+            table,
+            rows,
+            created,
+        })
+    }
+
+    fn rollback(self) {
+        drop(self.insert);
+        if let Err(err) = self.db.query("ROLLBACK") {
+            log::error!("Failed to roll back the import: {err}");
+        }
+    }
+}
+
+/// Import the rows of a csv file into `table`, posting the bytes processed so far.
+pub fn import_csv<F>(
+    db: &Arc<SQLiteDb>,
+    data: &[u8],
+    table: &str,
+    options: &CsvOptions,
+    post: &mut F,
+) -> Result<ImportedTable>
+where
+    F: FnMut(WorkerResponse),
+{
+    let csv_err = |err: csv::Error| WorkerError::ImportCsv(err.to_string());
+    let ascii = |c: char, what: &str| {
+        u8::try_from(c)
+            .ok()
+            .filter(u8::is_ascii)
+            .ok_or_else(|| WorkerError::ImportCsv(format!("the {what} must be an ASCII character")))
+    };
+
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(ascii(options.delimiter, "delimiter")?)
+        .has_headers(options.header)
+        .quoting(options.quote.is_some());
+    if let Some(quote) = options.quote {
+        builder.quote(ascii(quote, "quote")?);
+    }
+    let mut reader = builder.from_reader(data);
+
+    let names = if options.header {
+        let headers = reader.headers().map_err(csv_err)?;
+        let names = (1..)
+            .zip(headers)
+            .map(|(idx, name)| match name.trim() {
+                "" => format!("column{idx}"),
+                name => name.to_string(),
+            })
+            .collect::<Vec<_>>();
+        Some(names)
+    } else {
+        None
+    };
+
+    let mut records = reader.records();
+    let sample = records
+        .by_ref()
+        .take(SAMPLE_ROWS)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(csv_err)?;
+
+    let width = names
+        .as_ref()
+        .map(Vec::len)
+        .or_else(|| sample.first().map(csv::StringRecord::len))
+        .unwrap_or_default();
+    if width == 0 {
+        return Err(WorkerError::ImportCsv("the file has no columns".into()));
+    }
+
+    // the most general affinity of the fields, text if they are all empty
+    let affinities = (0..width)
+        .map(|idx| {
+            sample
+                .iter()
+                .filter_map(|record| record.get(idx).and_then(Affinity::of))
+                .max()
+                .unwrap_or(Affinity::Text)
+        })
+        .collect::<Vec<_>>();
+    let decl_types = affinities
+        .iter()
+        .map(|affinity| affinity.decl_type())
+        .collect::<Vec<_>>();
+
+    let mut writer = TableWriter::begin(db, table, names.as_deref(), &decl_types)?;
+    let mut row = Vec::with_capacity(width);
+    let result = sample
+        .into_iter()
+        .map(Ok)
+        .chain(records)
+        .try_for_each(|record| {
+            let record = record.map_err(csv_err)?;
+            row.clear();
+            row.extend(
+                affinities
+                    .iter()
+                    .zip(&record)
+                    .map(|(affinity, field)| affinity.value(field)),
+            );
+            writer.insert(&row)?;
+            if writer.rows % PROGRESS_ROWS == 0
+                && let Some(position) = record.position()
+            {
+                post(WorkerResponse::ImportProgress {
+                    processed: position.byte() as f64,
+                });
+            }
+            Ok(())
+        });
+
+    match result {
+        Ok(()) => writer.commit(),
+        Err(err) => {
+            writer.rollback();
+            Err(err)
+        }
+    }
+}
+
+/// Errors of more lines are left out.
+const MAX_LINE_ERRORS: usize = 10;
+
+/// A record of a json document, with the line it starts at.
+struct JsonRecord {
+    line: usize,
+    /// Byte offset of the end of the record.
+    end: usize,
+    fields: Map<String, Value>,
+}
+
+/// `line` is where the parsed text starts in the document.
+fn json_err(line: usize, err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    // the position is replaced by the one in the document
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message.as_str(), |(message, _)| message);
+    format!(
+        "line {}, column {}: {message}",
+        line + err.line() - 1,
+        err.column()
+    )
+}
+
+/// Nested values are kept as json text, booleans as 0 and 1.
+fn json_value(value: &Value) -> SQLiteValue {
+    match value {
+        Value::Null => SQLiteValue::Null,
+        Value::Bool(bool) => SQLiteValue::Integer(i64::from(*bool)),
+        Value::Number(number) => match number.as_i64() {
+            Some(number) => SQLiteValue::Integer(number),
+            None => number
+                .as_f64()
+                .map_or_else(|| SQLiteValue::Text(number.to_string()), SQLiteValue::Real),
+        },
+        Value::String(text) => SQLiteValue::Text(text.clone()),
+        Value::Array(_) | Value::Object(_) => SQLiteValue::Text(value.to_string()),
+    }
+}
+
+/// Parse the objects of a json array, or of each line of an ndjson document.
+///
+/// The errors of every invalid line are returned together.
+fn json_records(text: &str) -> Result<Vec<JsonRecord>> {
+    let record = |line: usize, end: usize, value: Value| match value {
+        Value::Object(fields) => Ok(JsonRecord { line, end, fields }),
+        _ => Err(format!("line {line}: the record is not an object")),
+    };
+
+    let parsed = if text.trim_start().starts_with('[') {
+        let elements = serde_json::from_str::<Vec<&RawValue>>(text)
+            .map_err(|err| WorkerError::ImportJson(json_err(1, &err)))?;
+        let (mut line, mut counted) = (1, 0);
+        elements
+            .into_iter()
+            .map(|element| {
+                // borrowed from `text`, the offset is where the element starts
+                let json = element.get();
+                let start = json.as_ptr() as usize - text.as_ptr() as usize;
+                line += text[counted..start].matches('\n').count();
+                counted = start;
+                // already validated as a json value
+                let value = serde_json::from_str(json).unwrap_or_default();
+                record(line, start + json.len(), value)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        let mut end = 0;
+        text.split_inclusive('\n')
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                end += line.len();
+                if line.trim().is_empty() {
+                    return None;
+                }
+                Some(match serde_json::from_str(line.trim_end()) {
+                    Ok(value) => record(idx + 1, end, value),
+                    Err(err) => Err(json_err(idx + 1, &err)),
+                })
+            })
+            .collect()
+    };
+
+    let (records, mut errors) =
+        parsed
+            .into_iter()
+            .fold((vec![], vec![]), |(mut records, mut errors), record| {
+                match record {
+                    Ok(record) => records.push(record),
+                    Err(err) => errors.push(err),
+                }
+                (records, errors)
+            });
+
+    if !errors.is_empty() {
+        let more = errors.len().saturating_sub(MAX_LINE_ERRORS);
+        errors.truncate(MAX_LINE_ERRORS);
+        if more > 0 {
+            errors.push(format!("and {more} more lines"));
+        }
+        return Err(WorkerError::ImportJson(errors.join("\n")));
+    }
+    Ok(records)
+}
+
+/// Import the objects of a json array or ndjson document into `table`.
+///
+/// The top-level keys of all objects are the columns, posting the bytes processed so far.
+pub fn import_json<F>(
+    db: &Arc<SQLiteDb>,
+    data: &[u8],
+    table: &str,
+    post: &mut F,
+) -> Result<ImportedTable>
+where
+    F: FnMut(WorkerResponse),
+{
+    let text = std::str::from_utf8(data)
+        .map_err(|err| WorkerError::ImportJson(format!("the file is not utf8 text: {err}")))?;
+    let records = json_records(text)?;
+
+    // in order of their first appearance
+    let mut names = Vec::<String>::new();
+    for record in &records {
+        for key in record.fields.keys() {
+            if !names.contains(key) {
+                names.push(key.clone());
+            }
+        }
+    }
+    if names.is_empty() {
+        return Err(WorkerError::ImportJson("the records have no keys".into()));
+    }
+
+    // the most general affinity of the values, text if they are all null
+    let affinities = names
+        .iter()
+        .map(|name| {
+            records
+                .iter()
+                .filter_map(|record| record.fields.get(name))
+                .filter_map(|value| Affinity::of_value(&json_value(value)))
+                .max()
+                .unwrap_or(Affinity::Text)
+        })
+        .collect::<Vec<_>>();
+    let decl_types = affinities
+        .iter()
+        .map(|affinity| affinity.decl_type())
+        .collect::<Vec<_>>();
+
+    let mut writer = TableWriter::begin(db, table, Some(&names), &decl_types)?;
+    let mut row = Vec::with_capacity(names.len());
+    let result = records.iter().try_for_each(|record| {
+        row.clear();
+        row.extend(names.iter().map(|name| {
+            record
+                .fields
+                .get(name)
+                .map_or(SQLiteValue::Null, json_value)
+        }));
+        writer
+            .insert(&row)
+            .map_err(|err| WorkerError::ImportJson(format!("line {}: {err}", record.line)))?;
+        if writer.rows % PROGRESS_ROWS == 0 {
+            post(WorkerResponse::ImportProgress {
+                processed: record.end as f64,
+            });
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => writer.commit(),
+        Err(err) => {
+            writer.rollback();
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn affinity_of_fields() {
+        assert_eq!(Affinity::of("007"), Some(Affinity::Text));
+        assert_eq!(Affinity::of(" 12"), Some(Affinity::Text));
+        assert_eq!(Affinity::of(""), None);
+        assert_eq!(Affinity::of("1e5"), Some(Affinity::Real));
+        assert_eq!(Affinity::of("9223372036854775808"), Some(Affinity::Real));
+        assert_eq!(Affinity::of("-0"), Some(Affinity::Integer));
+    }
+
+    #[test]
+    fn affinity_values() {
+        assert_eq!(
+            Affinity::Integer.value("007"),
+            SQLiteValue::Text("007".into())
+        );
+        assert_eq!(
+            Affinity::Integer.value(" 12"),
+            SQLiteValue::Text(" 12".into())
+        );
+        assert_eq!(Affinity::Integer.value(""), SQLiteValue::Null);
+        assert_eq!(Affinity::Real.value("1e5"), SQLiteValue::Real(1e5));
+        assert_eq!(
+            Affinity::Real.value("9223372036854775808"),
+            SQLiteValue::Real(9223372036854775808.0)
+        );
+        assert_eq!(Affinity::Integer.value("-0"), SQLiteValue::Integer(0));
+    }
+
+    fn json_error(text: &str) -> String {
+        match json_records(text) {
+            Err(WorkerError::ImportJson(message)) => message,
+            Err(err) => panic!("expected an import error, got {err:?}"),
+            Ok(_) => panic!("expected an import error"),
+        }
+    }
+
+    #[test]
+    fn json_error_lines() {
+        let ndjson = "{\"a\": 1}\n\n{\"a\": }\n{\"a\": 3}\n";
+        assert!(json_error(ndjson).starts_with("line 3, column 7: "));
+
+        let array = "[\n  {\"a\": 1},\n  {\"a\": }\n]";
+        assert!(json_error(array).starts_with("line 3, column 9: "));
+
+        let array = "[\n  {\"a\": 1},\n\n  2\n]";
+        assert_eq!(json_error(array), "line 4: the record is not an object");
+    }
+}
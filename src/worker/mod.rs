@@ -1,16 +1,27 @@
+mod compress;
+mod dump;
+mod import;
 mod sqlitend;
 
 use crate::{
-    DownloadDbResponse, LoadDbOptions, OpenOptions, RunOptions, SQLiteRunResult, WorkerError,
-    WorkerRequest, WorkerResponse,
+    AttachDbOptions, Attachment, CIPHERS, DatabaseAction, DownloadDbResponse, Encryption,
+    EncryptionAction, EncryptionStatus, ExplainOptions, ImportCsvOptions, ImportJsonOptions,
+    ImportedTable, InnerError, LoadDbOptions, OpenOptions, QueryPlanNode, RequestId, RunMode,
+    RunOptions, RunTarget, SERIALIZER, SQLiteParameter, SQLiteQueryPlan, SQLiteStatementTable,
+    SQLiteValue, SQLitendError, SchemaColumn, SchemaForeignKey, SchemaObject, SnapshotAction,
+    SnapshotInfo, WorkerError, WorkerRequest, WorkerRequestMessage, WorkerResponse,
+    WorkerResponseMessage, identifier,
 };
-use js_sys::Uint8Array;
+use js_sys::{Int32Array, Uint8Array};
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use sqlite_wasm_rs::{
+    SQLITE_INTERRUPT,
     mem_vfs::MemVfsUtil,
     sahpool_vfs::{OpfsSAHPoolCfgBuilder, OpfsSAHPoolUtil},
 };
 use sqlitend::SQLiteDb;
+use std::collections::VecDeque;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::sync::OnceCell;
@@ -21,6 +32,11 @@ use web_sys::{DedicatedWorkerGlobalScope, MessageEvent};
 
 type Result<T> = std::result::Result<T, WorkerError>;
 
+/// A chunk of rows is posted once it is this large...
+const CHUNK_MAX_ROWS: usize = 1000;
+/// ...or once it has been collected for this long, in milliseconds.
+const CHUNK_MAX_MILLIS: f64 = 100.0;
+
 static DB: Lazy<Mutex<Option<SQLiteWorker>>> = Lazy::new(|| Mutex::new(None));
 
 static FS_UTIL: Lazy<FSUtil> = Lazy::new(|| FSUtil {
@@ -43,10 +59,20 @@ const MEM_VFS: &str = "memvfs";
 #[cfg(feature = "sqlite3mc")]
 const MEM_VFS: &str = "multipleciphers-memvfs";
 
+/// Suffix of the file an encrypted db is verified in before replacing the opened one.
+const LOADING_SUFFIX: &str = "-loading";
+
+/// Suffix of the files of attached databases, they are not listed with the databases.
+const ATTACHED_SUFFIX: &str = ".attached.db";
+
 fn uri(filename: &str, persist: bool) -> String {
+    // these would end the path of the uri, or be decoded
+    let path = filename
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
     format!(
-        "file:{}?vfs={}",
-        filename,
+        "file:{path}?vfs={}",
         if persist { OPFS_VFS } else { MEM_VFS }
     )
 }
@@ -59,6 +85,119 @@ struct FSUtil {
 struct SQLiteWorker {
     open_options: OpenOptions,
     state: SQLiteState,
+    /// Oldest first, only of the main database.
+    snapshots: VecDeque<Snapshot>,
+    runs: u32,
+}
+
+/// A copy of the main database taken before a run.
+struct Snapshot {
+    run: u32,
+    sql: String,
+    page_size: usize,
+    data: Vec<u8>,
+}
+
+impl SQLiteWorker {
+    fn interrupt(&self) -> Option<&Int32Array> {
+        self.open_options.interrupt.dyn_ref()
+    }
+
+    fn open_db(&self) -> Result<Arc<SQLiteDb>> {
+        let persist = self.open_options.persist;
+        let db = SQLiteDb::open(
+            &uri(&self.open_options.filename, persist),
+            self.interrupt().cloned(),
+        )?;
+        if let Some(encryption) = &self.open_options.encryption {
+            set_key(&db, encryption)?;
+        }
+        for attachment in &self.open_options.attachments {
+            db.attach(&uri(&attachment.filename, persist), &attachment.alias)?;
+        }
+        Ok(db)
+    }
+
+    /// Keep a copy of the main database, dropping the oldest ones above `limit` bytes.
+    fn snapshot(&mut self, sql: &str, limit: usize) {
+        self.runs += 1;
+        let SQLiteState::Opened(sqlite_db) = &self.state else {
+            return;
+        };
+        if limit == 0 {
+            return;
+        }
+        // the header of an encrypted db is not readable
+        let (page_size, page_count) = match db_page_size(sqlite_db)
+            .and_then(|size| Ok((size, pragma_size(sqlite_db, "page_count")?)))
+        {
+            Ok(sizes) => sizes,
+            Err(err) => {
+                log::error!("Failed to snapshot db: {err}");
+                return;
+            }
+        };
+        // do not copy a db that can never be kept
+        if page_size * page_count > limit {
+            log::warn!(
+                "Skipped the snapshot, the db of {} bytes exceeds the limit",
+                page_size * page_count
+            );
+            return;
+        }
+        let data = match export_db(self.open_options.persist, &self.open_options.filename) {
+            Ok(data) => data,
+            Err(err) => {
+                log::error!("Failed to snapshot db: {err}");
+                return;
+            }
+        };
+        self.snapshots.push_back(Snapshot {
+            run: self.runs,
+            sql: sql.to_string(),
+            page_size,
+            data,
+        });
+        while self.snapshots.iter().map(|s| s.data.len()).sum::<usize>() > limit {
+            self.snapshots.pop_front();
+        }
+    }
+
+    fn snapshot_infos(&self) -> Vec<SnapshotInfo> {
+        self.snapshots
+            .iter()
+            .map(|snapshot| SnapshotInfo {
+                run: snapshot.run,
+                sql: snapshot.sql.clone(),
+                size: snapshot.data.len(),
+            })
+            .collect()
+    }
+}
+
+/// Quote a string literal.
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Select the cipher used by the following `key` or `rekey` pragma.
+fn set_cipher(db: &Arc<SQLiteDb>, encryption: &Encryption) -> Result<()> {
+    db.query(&format!("PRAGMA cipher = {}", literal(&encryption.cipher)))?;
+    db.query(&format!("PRAGMA legacy = {}", encryption.legacy))?;
+    Ok(())
+}
+
+/// Apply the key to a database that was just opened.
+fn set_key(db: &Arc<SQLiteDb>, encryption: &Encryption) -> Result<()> {
+    if encryption.key.is_empty() {
+        return Ok(());
+    }
+    set_cipher(db, encryption)?;
+    db.query(&format!("PRAGMA key = {}", literal(&encryption.key)))?;
+    // a wrong key is only noticed once the database is read
+    db.query("SELECT count(*) FROM sqlite_schema")
+        .map_err(|err| WorkerError::Encryption(format!("{err}")))?;
+    Ok(())
 }
 
 enum SQLiteState {
@@ -66,6 +205,28 @@ enum SQLiteState {
     Opened(Arc<SQLiteDb>),
 }
 
+fn is_interrupt(err: &SQLitendError) -> bool {
+    matches!(
+        err,
+        SQLitendError::Prepare(InnerError {
+            code: SQLITE_INTERRUPT,
+            ..
+        }) | SQLitendError::Step(InnerError {
+            code: SQLITE_INTERRUPT,
+            ..
+        })
+    )
+}
+
+/// Report an error caused by the interrupt flag as [`WorkerError::Interrupted`].
+fn interrupted(err: SQLitendError) -> WorkerError {
+    if is_interrupt(&err) {
+        WorkerError::Interrupted
+    } else {
+        WorkerError::SQLite(err)
+    }
+}
+
 async fn with_worker<F, T>(mut f: F) -> Result<T>
 where
     F: FnMut(&mut SQLiteWorker) -> Result<T>,
@@ -94,67 +255,425 @@ fn get_opfs_util() -> Result<&'static OpfsSAHPoolUtil> {
     FS_UTIL.opfs.get().ok_or(WorkerError::Unexpected)
 }
 
-async fn download_db() -> Result<DownloadDbResponse> {
+/// Read a db of the VFS.
+fn export_db(persist: bool, filename: &str) -> std::result::Result<Vec<u8>, String> {
+    if persist {
+        get_opfs_util()
+            .map_err(|err| format!("{err}"))?
+            .export_db(filename)
+            .map_err(|err| format!("{err}"))
+    } else {
+        let mem_vfs = &FS_UTIL.mem;
+        mem_vfs.export_db(filename).map_err(|err| format!("{err}"))
+    }
+}
+
+async fn download_db(compressed: bool) -> Result<DownloadDbResponse> {
     with_worker(|worker| {
-        let filename = &worker.open_options.filename;
-        let db = if worker.open_options.persist {
-            get_opfs_util()?
-                .export_db(filename)
-                .map_err(|err| WorkerError::DownloadDb(format!("{err}")))?
-        } else {
-            let mem_vfs = &FS_UTIL.mem;
-            mem_vfs
-                .export_db(filename)
-                .map_err(|err| WorkerError::DownloadDb(format!("{err}")))?
-        };
+        let mut db = export_db(worker.open_options.persist, &worker.open_options.filename)
+            .map_err(WorkerError::DownloadDb)?;
+        let mut filename = worker.open_options.filename.clone();
+        if compressed {
+            db = compress::gzip(&db).map_err(WorkerError::DownloadDb)?;
+            filename += ".gz";
+        }
         Ok(DownloadDbResponse {
-            filename: worker.open_options.filename.clone(),
+            filename,
             data: Uint8Array::new_from_slice(&db),
         })
     })
     .await
 }
 
-async fn load_db(options: LoadDbOptions) -> Result<()> {
-    let db = options.data.to_vec();
+async fn dump_db() -> Result<DownloadDbResponse> {
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+        let sql = dump::dump_db(sqlite_db)?;
+        let filename = &worker.open_options.filename;
+        let stem = filename
+            .rsplit_once('.')
+            .map_or(filename.as_str(), |(stem, _)| stem);
+        Ok(DownloadDbResponse {
+            filename: format!("{stem}.sql"),
+            data: Uint8Array::new_from_slice(sql.as_bytes()),
+        })
+    })
+    .await
+}
+
+/// All files of the VFS, including journals and attachments.
+fn list_files(persist: bool) -> Result<Vec<String>> {
+    Ok(if persist {
+        get_opfs_util()?.list()
+    } else {
+        FS_UTIL.mem.list()
+    })
+}
+
+/// Names of the databases of a VFS, without journals.
+fn list_dbs(persist: bool) -> Result<Vec<String>> {
+    let mut names = list_files(persist)?;
+    names.retain(|name| {
+        !["-journal", "-wal", "-shm", LOADING_SUFFIX, ATTACHED_SUFFIX]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    });
+    names.sort();
+    Ok(names)
+}
+
+fn copy_db(persist: bool, from: &str, to: &str) -> Result<()> {
+    let manage_err = |err: String| WorkerError::ManageDb(err);
 
-    #[cfg(feature = "sqlite3")]
-    let page_size = sqlite_wasm_rs::utils::check_import_db(&db)
-        .map_err(|err| WorkerError::LoadDb(format!("{err}")))?;
+    if list_dbs(persist)?.iter().any(|name| name == to) {
+        return Err(manage_err(format!("{to} already exists")));
+    }
 
-    #[cfg(feature = "sqlite3mc")]
-    let page_size = 65536;
+    let db = export_db(persist, from).map_err(manage_err)?;
+    // nothing to import, opening the db creates its file
+    if db.is_empty() {
+        drop(SQLiteDb::open(&uri(to, persist), None)?);
+        return Ok(());
+    }
 
+    if persist {
+        get_opfs_util()?
+            .import_db_unchecked(to, &db)
+            .map_err(|err| manage_err(format!("{err}")))?;
+    } else {
+        // an encrypted db keeps the page size in its header in plain text
+        let page_size = plain_page_size(&db)
+            .ok()
+            .or_else(|| encrypted_page_sizes(&db).first().copied())
+            .ok_or_else(|| manage_err(format!("{from} is not a SQLite database")))?;
+        FS_UTIL
+            .mem
+            .import_db_unchecked(to, &db, page_size)
+            .map_err(|err| manage_err(format!("{err}")))?;
+    }
+    Ok(())
+}
+
+fn delete_db(persist: bool, filename: &str) -> Result<()> {
+    if persist {
+        get_opfs_util()?
+            .delete_db(filename)
+            .map_err(|err| WorkerError::ManageDb(format!("{err}")))?;
+    } else {
+        FS_UTIL.mem.delete_db(filename);
+    }
+    Ok(())
+}
+
+async fn databases(action: DatabaseAction) -> Result<Vec<String>> {
     with_worker(|worker| {
-        drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
+        let persist = worker.open_options.persist;
+        let opened = worker.open_options.filename.clone();
 
-        let filename = &worker.open_options.filename;
-        if worker.open_options.persist {
-            let opfs = get_opfs_util()?;
-            opfs.delete_db(filename)
-                .map_err(|_| WorkerError::Unexpected)?;
+        match &action {
+            DatabaseAction::List => (),
+            DatabaseAction::Rename { from, to } => {
+                // fail before the opened db is closed
+                if list_dbs(persist)?.contains(to) {
+                    return Err(WorkerError::ManageDb(format!("{to} already exists")));
+                }
+                let close = *from == opened;
+                if close {
+                    drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
+                }
+                let renamed = copy_db(persist, from, to).and_then(|()| delete_db(persist, from));
+                if close && renamed.is_err() {
+                    worker.state = SQLiteState::Opened(worker.open_db()?);
+                }
+                renamed?;
+            }
+            DatabaseAction::Duplicate { from, to } => copy_db(persist, from, to)?,
+            DatabaseAction::Delete(filename) => {
+                if *filename == opened {
+                    drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
+                }
+                delete_db(persist, filename)?;
+            }
+        }
+
+        list_dbs(persist)
+    })
+    .await
+}
+
+/// Page size of an unencrypted db, read from its header.
+fn plain_page_size(db: &[u8]) -> std::result::Result<usize, String> {
+    sqlite_wasm_rs::utils::check_import_db(db).map_err(|err| format!("{err}"))
+}
+
+/// Page sizes an encrypted db may use, most likely first.
+///
+/// Most ciphers leave bytes 16 to 23 of the header unencrypted.
+fn encrypted_page_sizes(db: &[u8]) -> Vec<usize> {
+    let header = match db.get(16..18) {
+        Some([1, 0]) => 65536,
+        Some(&[high, low]) => usize::from(u16::from_be_bytes([high, low])),
+        _ => 0,
+    };
+    let mut sizes = vec![];
+    for size in [header, 4096, 1024, 8192, 16384, 32768, 65536, 512, 2048] {
+        if size.is_power_of_two()
+            && size >= 512
+            && db.len().is_multiple_of(size)
+            && !sizes.contains(&size)
+        {
+            sizes.push(size);
+        }
+    }
+    sizes
+}
+
+fn db_page_size(db: &Arc<SQLiteDb>) -> Result<usize> {
+    pragma_size(db, "page_size")
+}
+
+fn pragma_size(db: &Arc<SQLiteDb>, pragma: &str) -> Result<usize> {
+    match db
+        .query(&format!("PRAGMA {pragma}"))?
+        .first()
+        .map(Vec::as_slice)
+    {
+        Some([SQLiteValue::Integer(size), ..]) => {
+            usize::try_from(*size).map_err(|_| WorkerError::Unexpected)
+        }
+        _ => Err(WorkerError::Unexpected),
+    }
+}
+
+/// Write a db into the VFS, replacing the file if it exists.
+fn import_db(
+    persist: bool,
+    filename: &str,
+    db: &[u8],
+    page_size: usize,
+) -> std::result::Result<(), String> {
+    if persist {
+        let opfs = get_opfs_util().map_err(|err| format!("{err}"))?;
+        opfs.delete_db(filename).map_err(|err| format!("{err}"))?;
+        opfs.import_db_unchecked(filename, db)
+            .map_err(|err| format!("{err}"))
+    } else {
+        let mem_vfs = &FS_UTIL.mem;
+        mem_vfs.delete_db(filename);
+        mem_vfs
+            .import_db_unchecked(filename, db, page_size)
+            .map_err(|err| format!("{err}"))
+    }
+}
 
-            if let Err(err) = opfs.import_db_unchecked(filename, &db) {
-                return Err(WorkerError::LoadDb(format!("{err}")));
+/// The page size an encrypted db is read with, the key is verified on a temporary file.
+fn verify_encrypted(
+    persist: bool,
+    filename: &str,
+    db: &[u8],
+    encryption: &Encryption,
+) -> Result<usize> {
+    let page_sizes = encrypted_page_sizes(db);
+    if page_sizes.is_empty() {
+        return Err(WorkerError::LoadDb("not a SQLite database".into()));
+    }
+
+    let temp = format!("{filename}{LOADING_SUFFIX}");
+    let result = (|| {
+        for page_size in page_sizes {
+            import_db(persist, &temp, db, page_size).map_err(WorkerError::LoadDb)?;
+            let sqlite_db = SQLiteDb::open(&uri(&temp, persist), None)?;
+            match set_key(&sqlite_db, encryption) {
+                Ok(()) => return db_page_size(&sqlite_db),
+                Err(WorkerError::Encryption(_)) => continue,
+                Err(err) => return Err(err),
             }
-        } else {
-            let mem_vfs = &FS_UTIL.mem;
-            mem_vfs.delete_db(filename);
-            if let Err(err) = mem_vfs.import_db_unchecked(filename, &db, page_size) {
-                return Err(WorkerError::LoadDb(format!("{err}")));
+        }
+        Err(WorkerError::LoadDb(
+            "wrong key, or not a SQLite database".into(),
+        ))
+    })();
+    if let Err(err) = delete_db(persist, &temp) {
+        log::error!("Failed to delete {temp}: {err}");
+    }
+    result
+}
+
+/// Import an encrypted db, the opened db is only replaced once the key can read it.
+fn load_encrypted(worker: &mut SQLiteWorker, db: &[u8], encryption: &Encryption) -> Result<()> {
+    if plain_page_size(db).is_ok() {
+        return Err(WorkerError::LoadDb("not encrypted".into()));
+    }
+    if encryption.key.is_empty() {
+        return Err(WorkerError::LoadDb("empty key".into()));
+    }
+    if !CIPHERS.contains(&encryption.cipher.as_str()) {
+        return Err(WorkerError::LoadDb(format!(
+            "unsupported cipher {}",
+            encryption.cipher
+        )));
+    }
+
+    let persist = worker.open_options.persist;
+    let filename = worker.open_options.filename.clone();
+    let page_size = verify_encrypted(persist, &filename, db, encryption)?;
+
+    drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
+    import_db(persist, &filename, db, page_size).map_err(WorkerError::LoadDb)?;
+    worker.open_options.encryption = Some(encryption.clone());
+    worker.state = SQLiteState::Opened(worker.open_db()?);
+    Ok(())
+}
+
+fn load_plain(worker: &mut SQLiteWorker, db: &[u8]) -> Result<()> {
+    let page_size = plain_page_size(db).map_err(WorkerError::LoadDb)?;
+
+    drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
+    import_db(
+        worker.open_options.persist,
+        &worker.open_options.filename,
+        db,
+        page_size,
+    )
+    .map_err(WorkerError::LoadDb)?;
+    worker.open_options.encryption = None;
+    worker.state = SQLiteState::Opened(worker.open_db()?);
+    Ok(())
+}
+
+async fn load_db(options: LoadDbOptions) -> Result<Option<Encryption>> {
+    let db = compress::decompress(options.data.to_vec()).map_err(WorkerError::LoadDb)?;
+
+    with_worker(|worker| {
+        let result = match &options.encryption {
+            Some(encryption) => load_encrypted(worker, &db, encryption),
+            None => load_plain(worker, &db),
+        };
+        // keep the worker usable if the db was closed before failing
+        if result.is_err()
+            && matches!(worker.state, SQLiteState::NotOpened)
+            && let Ok(sqlite_db) = worker.open_db()
+        {
+            worker.state = SQLiteState::Opened(sqlite_db);
+        }
+        result?;
+        Ok(worker.open_options.encryption.clone())
+    })
+    .await
+}
+
+async fn snapshots(action: SnapshotAction) -> Result<Vec<SnapshotInfo>> {
+    with_worker(|worker| {
+        if let SnapshotAction::Revert(run) = action {
+            let Some(snapshot) = worker.snapshots.iter().find(|s| s.run == run) else {
+                return Err(WorkerError::LoadDb(format!(
+                    "No snapshot before run #{run}"
+                )));
+            };
+            drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
+            import_db(
+                worker.open_options.persist,
+                &worker.open_options.filename,
+                &snapshot.data,
+                snapshot.page_size,
+            )
+            .map_err(WorkerError::LoadDb)?;
+            worker.state = SQLiteState::Opened(worker.open_db()?);
+        }
+        Ok(worker.snapshot_infos())
+    })
+    .await
+}
+
+async fn encryption(action: EncryptionAction) -> Result<EncryptionStatus> {
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+        if let EncryptionAction::Rekey(encryption) = &action {
+            match encryption {
+                Some(encryption) => {
+                    set_cipher(sqlite_db, encryption)?;
+                    sqlite_db.query(&format!("PRAGMA rekey = {}", literal(&encryption.key)))?;
+                }
+                None => {
+                    sqlite_db.query("PRAGMA rekey = ''")?;
+                }
             }
+            worker.open_options.encryption = encryption.clone();
+        }
+
+        let cipher = match sqlite_db.query("PRAGMA cipher")?.first().map(Vec::as_slice) {
+            Some([SQLiteValue::Text(cipher), ..]) => cipher.clone(),
+            _ => String::new(),
+        };
+        let encrypted = worker
+            .open_options
+            .encryption
+            .as_ref()
+            .is_some_and(|encryption| !encryption.key.is_empty());
+        Ok(EncryptionStatus { cipher, encrypted })
+    })
+    .await
+}
+
+async fn attach_db(options: AttachDbOptions) -> Result<Attachment> {
+    let db = options.data.to_vec();
+
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+        // schema names are case-insensitive
+        let attachments = &worker.open_options.attachments;
+        if ["main", "temp"]
+            .into_iter()
+            .chain(attachments.iter().map(|a| a.alias.as_str()))
+            .any(|alias| alias.eq_ignore_ascii_case(&options.alias))
+        {
+            return Err(WorkerError::AttachDb(format!(
+                "{} is already in use",
+                options.alias
+            )));
+        }
+
+        let attachment = Attachment {
+            alias: options.alias.clone(),
+            filename: format!("{}{ATTACHED_SUFFIX}", options.alias),
+        };
+        let persist = worker.open_options.persist;
+        let page_size = plain_page_size(&db).map_err(WorkerError::AttachDb)?;
+        import_db(persist, &attachment.filename, &db, page_size).map_err(WorkerError::AttachDb)?;
+        if let Err(err) = sqlite_db.attach(&uri(&attachment.filename, persist), &attachment.alias) {
+            delete_db(persist, &attachment.filename)?;
+            return Err(err.into());
         }
 
-        worker.state = SQLiteState::Opened(SQLiteDb::open(&uri(
-            &worker.open_options.filename,
-            worker.open_options.persist,
-        ))?);
-        Ok(())
+        worker.open_options.attachments.push(attachment.clone());
+        Ok(attachment)
+    })
+    .await
+}
+
+async fn detach_db(alias: String) -> Result<String> {
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+        sqlite_db.detach(&alias)?;
+        let attachments = &mut worker.open_options.attachments;
+        if let Some(idx) = attachments.iter().position(|a| a.alias == alias) {
+            let attachment = attachments.remove(idx);
+            delete_db(worker.open_options.persist, &attachment.filename)?;
+        }
+        Ok(alias.clone())
     })
     .await
 }
 
-async fn open(options: OpenOptions) -> Result<()> {
+async fn open(mut options: OpenOptions) -> Result<Vec<Attachment>> {
     let mut locker = DB.lock().await;
     locker.take();
 
@@ -162,18 +681,115 @@ async fn open(options: OpenOptions) -> Result<()> {
         init_opfs_util().await?;
     }
 
-    let state = SQLiteState::Opened(SQLiteDb::open(&uri(&options.filename, options.persist))?);
-    let worker = SQLiteWorker {
+    // e.g. the memory VFS is empty after a reload, do not attach empty dbs
+    let files = list_files(options.persist)?;
+    options.attachments.retain(|attachment| {
+        let exists = files.contains(&attachment.filename);
+        if !exists {
+            log::warn!("{} is gone, it is not attached", attachment.alias);
+        }
+        exists
+    });
+
+    let mut worker = SQLiteWorker {
         open_options: options,
-        state,
+        state: SQLiteState::NotOpened,
+        snapshots: VecDeque::new(),
+        runs: 0,
     };
+    worker.state = SQLiteState::Opened(worker.open_db()?);
+    let attachments = worker.open_options.attachments.clone();
     *locker = Some(worker);
+    Ok(attachments)
+}
+
+/// Values of [`WorkerRequest::ParameterValues`], received while a run is paused.
+type ParameterValues = UnboundedReceiver<Option<Vec<SQLiteParameter>>>;
+
+/// Execute the statements one by one, posting their rows.
+///
+/// Pauses for the values of parameters that were not asked for before the run.
+async fn run_statements<F>(
+    sqlite_db: &Arc<SQLiteDb>,
+    options: &mut RunOptions,
+    post: &mut F,
+    values: &mut ParameterValues,
+) -> Result<()>
+where
+    F: FnMut(WorkerResponse),
+{
+    let mut stmts = sqlite_db.prepare(&options.sql)?;
+    let failed = |position, error: SQLitendError| {
+        if !options.continue_on_error || is_interrupt(&error) {
+            return Err(interrupted(error));
+        }
+        Ok(WorkerResponse::StatementFailed { position, error })
+    };
+
+    loop {
+        let stmt = match stmts.prepare_next() {
+            Ok(Some(stmt)) => stmt,
+            Ok(None) => break,
+            Err(err) => {
+                post(failed(stmts.skip_next()?, err)?);
+                continue;
+            }
+        };
+        if options.target != RunTarget::Bytecode {
+            let mut names = stmt.parameters();
+            names.retain(|name| !options.asked.contains(name));
+            if !names.is_empty() {
+                post(WorkerResponse::ParametersNeeded(names.clone()));
+                let Some(Some(parameters)) = values.recv().await else {
+                    return Err(WorkerError::Interrupted);
+                };
+                options
+                    .parameters
+                    .retain(|param| !parameters.iter().any(|value| value.name == param.name));
+                options.parameters.extend(parameters);
+                options.asked.extend(names);
+            }
+        }
+        let prepared = if options.target == RunTarget::Bytecode {
+            stmt.set_explain(1)
+        } else {
+            stmt.bind(&options.parameters)
+        };
+        if let Err(err) = prepared {
+            post(failed(stmt.position(), err)?);
+            continue;
+        }
+        let result = stmt.get_chunks(CHUNK_MAX_ROWS, CHUNK_MAX_MILLIS, |values| {
+            post(WorkerResponse::RowsChunk(stmt.pack(Some(values))));
+        });
+        match result {
+            Ok(metrics) => post(WorkerResponse::StatementDone(SQLiteStatementTable {
+                metrics: Some(metrics),
+                ..stmt.pack(None)
+            })),
+            Err(err) => post(failed(stmt.position(), err)?),
+        }
+    }
     Ok(())
 }
 
-async fn run(options: RunOptions) -> Result<SQLiteRunResult> {
-    with_worker(|worker| {
-        if options.clear_on_prepare {
+async fn run<F>(mut options: RunOptions, mut post: F, values: &mut ParameterValues) -> Result<()>
+where
+    F: FnMut(WorkerResponse),
+{
+    // values sent after the previous run finished
+    while values.try_recv().is_ok() {}
+
+    // held while paused for parameters, the run is not over
+    let mut locker = DB.lock().await;
+    let worker = locker.as_mut().ok_or(WorkerError::NotOpened)?;
+    {
+        // nothing to revert after a dry run or explaining the statements
+        if options.mode != RunMode::DryRun && options.target != RunTarget::Bytecode {
+            worker.snapshot(&options.sql, options.snapshot_limit);
+        }
+
+        if options.mode == RunMode::DropContext {
             drop(std::mem::replace(&mut worker.state, SQLiteState::NotOpened));
 
             let filename = &worker.open_options.filename;
@@ -186,38 +802,346 @@ async fn run(options: RunOptions) -> Result<SQLiteRunResult> {
                 mem_vfs.delete_db(filename);
             }
 
-            worker.state = SQLiteState::Opened(SQLiteDb::open(&uri(
-                &worker.open_options.filename,
-                worker.open_options.persist,
-            ))?);
+            worker.state = SQLiteState::Opened(worker.open_db()?);
         }
         match &worker.state {
             SQLiteState::NotOpened => Err(WorkerError::InvaildState),
             SQLiteState::Opened(sqlite_db) => {
-                let stmts = sqlite_db.prepare(&options.sql)?;
-                let result = stmts.stmts_result()?;
-                Ok(SQLiteRunResult {
-                    embed: options.embed,
-                    result,
-                })
+                let dry_run = options.mode == RunMode::DryRun;
+                post(WorkerResponse::RunStarted {
+                    target: options.target,
+                    dry_run,
+                });
+
+                if !dry_run {
+                    return run_statements(sqlite_db, &mut options, &mut post, values).await;
+                }
+
+                sqlite_db.begin_dry_run()?;
+                let result = run_statements(sqlite_db, &mut options, &mut post, values).await;
+                // an interrupted run must still be rolled back
+                if let Some(flag) = worker.interrupt() {
+                    js_sys::Atomics::store(flag, 0, 0).map_err(|_| WorkerError::Unexpected)?;
+                }
+                let rollback = sqlite_db.end_dry_run();
+                result?;
+                Ok(rollback?)
+            }
+        }
+    }
+}
+
+async fn import_csv<F>(options: ImportCsvOptions, mut post: F) -> Result<ImportedTable>
+where
+    F: FnMut(WorkerResponse),
+{
+    let data = options.data.to_vec();
+
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+        import::import_csv(
+            sqlite_db,
+            &data,
+            &options.table,
+            &options.options,
+            &mut post,
+        )
+    })
+    .await
+}
+
+async fn import_json<F>(options: ImportJsonOptions, mut post: F) -> Result<ImportedTable>
+where
+    F: FnMut(WorkerResponse),
+{
+    let data = options.data.to_vec();
+
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+        import::import_json(sqlite_db, &data, &options.table, &mut post)
+    })
+    .await
+}
+
+async fn parameters(sql: String) -> Result<Vec<String>> {
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+
+        // statements that depend on earlier ones may fail to prepare,
+        // the run asks for their parameters once it prepares them
+        let mut stmts = sqlite_db.prepare(&sql)?;
+        let mut names: Vec<String> = vec![];
+        loop {
+            match stmts.prepare_next() {
+                Ok(Some(stmt)) => {
+                    for name in stmt.parameters() {
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    stmts.skip_next()?;
+                }
             }
         }
+        Ok(names)
+    })
+    .await
+}
+
+async fn query_plan(options: ExplainOptions) -> Result<Vec<SQLiteQueryPlan>> {
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+
+        let mut stmts = sqlite_db.prepare(&options.sql)?;
+        let mut plans = vec![];
+        loop {
+            let plan = match stmts.prepare_next() {
+                Ok(Some(stmt)) => SQLiteQueryPlan {
+                    sql: stmt.sql().trim().to_string(),
+                    position: stmt.position(),
+                    nodes: stmt.explain(2).map(|values| {
+                        values
+                            .map(|values| values.rows)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter_map(|row| match <[SQLiteValue; 4]>::try_from(row) {
+                                Ok(
+                                    [
+                                        SQLiteValue::Integer(id),
+                                        SQLiteValue::Integer(parent),
+                                        _,
+                                        SQLiteValue::Text(detail),
+                                    ],
+                                ) => Some(QueryPlanNode { id, parent, detail }),
+                                _ => None,
+                            })
+                            .collect()
+                    }),
+                },
+                Ok(None) => break,
+                Err(err) => {
+                    let position = stmts.skip_next()?;
+                    SQLiteQueryPlan {
+                        sql: options.sql[position[0]..position[1]].trim().to_string(),
+                        position,
+                        nodes: Err(err),
+                    }
+                }
+            };
+            plans.push(plan);
+        }
+
+        // the statement ending at or after the cursor, or the last one
+        if let Some(cursor) = options.cursor {
+            let idx = plans
+                .iter()
+                .position(|plan| plan.position[1] >= cursor)
+                .unwrap_or(plans.len().saturating_sub(1));
+            plans = plans.into_iter().skip(idx).take(1).collect();
+        }
+
+        Ok(plans)
+    })
+    .await
+}
+
+fn schema_columns(db: &Arc<SQLiteDb>, schema: &str, name: &str) -> Vec<SchemaColumn> {
+    let sql = format!(
+        "SELECT name, type, \"notnull\", dflt_value, pk, hidden FROM pragma_table_xinfo({}, {})",
+        SQLiteValue::Text(name.into()),
+        SQLiteValue::Text(schema.into())
+    );
+    // e.g. virtual tables of a module that is not loaded
+    let Ok(rows) = db.query(&sql) else {
+        return vec![];
+    };
+    rows.into_iter()
+        .filter_map(|row| match <[SQLiteValue; 6]>::try_from(row) {
+            Ok(
+                [
+                    SQLiteValue::Text(name),
+                    SQLiteValue::Text(decl_type),
+                    SQLiteValue::Integer(not_null),
+                    default,
+                    SQLiteValue::Integer(pk),
+                    SQLiteValue::Integer(hidden),
+                ],
+            ) if hidden != 1 => Some(SchemaColumn {
+                name,
+                decl_type,
+                not_null: not_null != 0,
+                default: match default {
+                    SQLiteValue::Text(default) => Some(default),
+                    _ => None,
+                },
+                primary_key: pk > 0,
+                generated: hidden > 1,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn schema_foreign_keys(db: &Arc<SQLiteDb>, schema: &str, name: &str) -> Vec<SchemaForeignKey> {
+    let sql = format!(
+        "SELECT \"from\", \"table\", \"to\" FROM pragma_foreign_key_list({}, {})",
+        SQLiteValue::Text(name.into()),
+        SQLiteValue::Text(schema.into())
+    );
+    let Ok(rows) = db.query(&sql) else {
+        return vec![];
+    };
+    rows.into_iter()
+        .filter_map(|row| match <[SQLiteValue; 3]>::try_from(row) {
+            Ok([SQLiteValue::Text(from), SQLiteValue::Text(table), to]) => Some(SchemaForeignKey {
+                from,
+                table,
+                to: match to {
+                    SQLiteValue::Text(to) => Some(to),
+                    _ => None,
+                },
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+async fn schema() -> Result<Vec<SchemaObject>> {
+    with_worker(|worker| {
+        let SQLiteState::Opened(sqlite_db) = &worker.state else {
+            return Err(WorkerError::InvaildState);
+        };
+
+        let schemas = sqlite_db
+            .query("SELECT name FROM pragma_database_list")?
+            .into_iter()
+            .filter_map(|row| match row.into_iter().next() {
+                Some(SQLiteValue::Text(schema)) => Some(schema),
+                _ => None,
+            });
+        let mut rows = vec![];
+        for schema in schemas {
+            let sql = format!(
+                "SELECT type, name, tbl_name, sql FROM {}.sqlite_schema ORDER BY name",
+                identifier(&schema)
+            );
+            rows.extend(
+                sqlite_db
+                    .query(&sql)?
+                    .into_iter()
+                    .map(|row| (schema.clone(), row)),
+            );
+        }
+
+        let mut objects = vec![];
+        for (schema, row) in rows {
+            let Ok(
+                [
+                    SQLiteValue::Text(kind),
+                    SQLiteValue::Text(name),
+                    SQLiteValue::Text(table),
+                    sql,
+                ],
+            ) = <[SQLiteValue; 4]>::try_from(row)
+            else {
+                continue;
+            };
+            let columns = match kind.as_str() {
+                "table" | "view" => schema_columns(sqlite_db, &schema, &name),
+                _ => vec![],
+            };
+            let foreign_keys = match kind.as_str() {
+                "table" => schema_foreign_keys(sqlite_db, &schema, &name),
+                _ => vec![],
+            };
+            objects.push(SchemaObject {
+                schema,
+                kind,
+                name,
+                table,
+                sql: match sql {
+                    SQLiteValue::Text(sql) => Some(sql),
+                    _ => None,
+                },
+                columns,
+                foreign_keys,
+            });
+        }
+        Ok(objects)
     })
     .await
 }
 
-async fn execute_task(scope: DedicatedWorkerGlobalScope, mut rx: UnboundedReceiver<JsValue>) {
-    while let Some(request) = rx.recv().await {
-        let request = serde_wasm_bindgen::from_value::<WorkerRequest>(request).unwrap();
+fn post_response(scope: &DedicatedWorkerGlobalScope, id: RequestId, response: WorkerResponse) {
+    let message = WorkerResponseMessage {
+        id: Some(id),
+        response,
+    };
+    if let Err(err) = scope.post_message(&message.serialize(&SERIALIZER).unwrap()) {
+        log::error!("Failed to send task to window: {message:?}, {err:?}");
+    }
+}
+
+/// Clear a stop that arrived after the previous request finished.
+async fn reset_interrupt() {
+    if let Some(worker) = &*DB.lock().await
+        && let Some(flag) = worker.interrupt()
+        && js_sys::Atomics::store(flag, 0, 0).is_err()
+    {
+        log::error!("Failed to reset the interrupt flag");
+    }
+}
+
+async fn execute_task(
+    scope: DedicatedWorkerGlobalScope,
+    mut rx: UnboundedReceiver<WorkerRequestMessage>,
+    mut values: ParameterValues,
+) {
+    while let Some(WorkerRequestMessage { id, request }) = rx.recv().await {
+        reset_interrupt().await;
         let resp = match request {
             WorkerRequest::Open(options) => WorkerResponse::Open(open(options).await),
-            WorkerRequest::Run(options) => WorkerResponse::Run(run(options).await),
+            WorkerRequest::Run(options) => WorkerResponse::RunFinished(
+                run(options, |resp| post_response(&scope, id, resp), &mut values).await,
+            ),
             WorkerRequest::LoadDb(options) => WorkerResponse::LoadDb(load_db(options).await),
-            WorkerRequest::DownloadDb => WorkerResponse::DownloadDb(download_db().await),
+            WorkerRequest::ImportCsv(options) => WorkerResponse::ImportCsv(
+                import_csv(options, |resp| post_response(&scope, id, resp)).await,
+            ),
+            WorkerRequest::ImportJson(options) => WorkerResponse::ImportJson(
+                import_json(options, |resp| post_response(&scope, id, resp)).await,
+            ),
+            WorkerRequest::DownloadDb { compressed } => {
+                WorkerResponse::DownloadDb(download_db(compressed).await)
+            }
+            WorkerRequest::DumpDb => WorkerResponse::DumpDb(dump_db().await),
+            WorkerRequest::QueryPlan(options) => {
+                WorkerResponse::QueryPlan(query_plan(options).await)
+            }
+            WorkerRequest::Schema => WorkerResponse::Schema(schema().await),
+            WorkerRequest::AttachDb(options) => WorkerResponse::AttachDb(attach_db(options).await),
+            WorkerRequest::DetachDb(alias) => WorkerResponse::DetachDb(detach_db(alias).await),
+            WorkerRequest::Databases(action) => WorkerResponse::Databases(databases(action).await),
+            WorkerRequest::Parameters(sql) => WorkerResponse::Parameters(parameters(sql).await),
+            // only sent while a run is paused
+            WorkerRequest::ParameterValues(_) => continue,
+            WorkerRequest::Snapshots(action) => WorkerResponse::Snapshots(snapshots(action).await),
+            WorkerRequest::Encryption(action) => {
+                WorkerResponse::Encryption(encryption(action).await)
+            }
         };
-        if let Err(err) = scope.post_message(&serde_wasm_bindgen::to_value(&resp).unwrap()) {
-            log::error!("Failed to send task to window: {resp:?}, {err:?}");
-        }
+        post_response(&scope, id, resp);
     }
 }
 
@@ -225,18 +1149,28 @@ pub fn entry() {
     console_error_panic_hook::set_once();
     console_log::init_with_level(log::Level::Debug).unwrap();
 
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<JsValue>();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    // not queued behind the paused run
+    let (values_tx, values_rx) = tokio::sync::mpsc::unbounded_channel();
 
     let scope: DedicatedWorkerGlobalScope = JsValue::from(js_sys::global()).into();
-    spawn_local(execute_task(scope.clone(), rx));
+    spawn_local(execute_task(scope.clone(), rx, values_rx));
 
     let on_message = Closure::<dyn Fn(MessageEvent)>::new(move |ev: MessageEvent| {
-        tx.send(ev.data()).unwrap();
+        let message: WorkerRequestMessage = serde_wasm_bindgen::from_value(ev.data()).unwrap();
+        match message.request {
+            WorkerRequest::ParameterValues(values) => values_tx.send(values).unwrap(),
+            _ => tx.send(message).unwrap(),
+        }
     });
 
     scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    let ready = WorkerResponseMessage {
+        id: None,
+        response: WorkerResponse::Ready,
+    };
     scope
-        .post_message(&serde_wasm_bindgen::to_value(&WorkerResponse::Ready).unwrap())
+        .post_message(&ready.serialize(&SERIALIZER).unwrap())
         .expect("Faild to send ready to window");
     on_message.forget();
 }
@@ -1,10 +1,13 @@
+use js_sys::Int32Array;
 use sqlite_wasm_rs::*;
 use sqlite_wasm_vec::sqlite3_vec_init;
-use std::ffi::{CStr, CString};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
 use std::sync::Arc;
+use wasm_bindgen::JsCast;
 
 use crate::{
-    InnerError, SQLiteStatementResult, SQLiteStatementTable, SQLiteStatementValues, SQLitendError,
+    InnerError, ParameterKind, SQLiteParameter, SQLiteStatementMetrics, SQLiteStatementTable,
+    SQLiteStatementValues, SQLiteValue, SQLitendError, identifier,
 };
 
 type Result<T> = std::result::Result<T, SQLitendError>;
@@ -21,17 +24,70 @@ fn sqlite_err(code: i32, db: *mut sqlite3) -> InnerError {
     InnerError { code, message }
 }
 
+/// High resolution timestamps in milliseconds, the `Performance` object is looked up once.
+fn clock() -> impl Fn() -> f64 {
+    let performance = js_sys::global()
+        .unchecked_into::<web_sys::WorkerGlobalScope>()
+        .performance();
+    move || {
+        performance
+            .as_ref()
+            .map_or_else(js_sys::Date::now, web_sys::Performance::now)
+    }
+}
+
+/// Polled by SQLite while a statement is running, a non-zero flag interrupts it.
+unsafe extern "C" fn progress_handler(flag: *mut c_void) -> c_int {
+    let flag = unsafe { &*flag.cast::<Int32Array>() };
+    match js_sys::Atomics::load(flag, 0) {
+        Ok(0) | Err(_) => 0,
+        Ok(_) => 1,
+    }
+}
+
+/// Name of the savepoint of a dry run.
+const DRY_RUN_SAVEPOINT: &CStr = c"sqlight_dry_run";
+
+/// Denies statements that would end the savepoint of a dry run.
+unsafe extern "C" fn dry_run_authorizer(
+    _: *mut c_void,
+    action: c_int,
+    _: *const c_char,
+    name: *const c_char,
+    _: *const c_char,
+    _: *const c_char,
+) -> c_int {
+    match action {
+        SQLITE_TRANSACTION => SQLITE_DENY,
+        SQLITE_SAVEPOINT
+            if !name.is_null() && unsafe { CStr::from_ptr(name) } == DRY_RUN_SAVEPOINT =>
+        {
+            SQLITE_DENY
+        }
+        _ => SQLITE_OK,
+    }
+}
+
 pub struct SQLiteDb {
     sqlite3: *mut sqlite3,
+    // read by the progress handler, must outlive the connection
+    _interrupt: Option<Box<Int32Array>>,
 }
 
 unsafe impl Send for SQLiteDb {}
 unsafe impl Sync for SQLiteDb {}
 
 impl SQLiteDb {
-    pub fn open(filename: &str) -> Result<Arc<Self>> {
+    pub fn open(filename: &str, interrupt: Option<Int32Array>) -> Result<Arc<Self>> {
         unsafe {
-            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
+            sqlite3_auto_extension(Some(std::mem::transmute::<
+                *const (),
+                unsafe extern "C" fn(
+                    *mut sqlite3,
+                    *mut *mut std::ffi::c_char,
+                    *const sqlite3_api_routines,
+                ) -> i32,
+            >(sqlite3_vec_init as *const ())));
         }
 
         let mut sqlite3 = std::ptr::null_mut();
@@ -48,7 +104,73 @@ impl SQLiteDb {
             return Err(SQLitendError::OpenDb(sqlite_err(ret, sqlite3)));
         }
 
-        Ok(Arc::new(Self { sqlite3 }))
+        let interrupt = interrupt.map(Box::new);
+        if let Some(flag) = &interrupt {
+            unsafe {
+                sqlite3_progress_handler(
+                    sqlite3,
+                    1000,
+                    Some(progress_handler),
+                    (&**flag as *const Int32Array).cast_mut().cast(),
+                );
+            }
+        }
+
+        Ok(Arc::new(Self {
+            sqlite3,
+            _interrupt: interrupt,
+        }))
+    }
+
+    /// Run a single statement and get all of its rows
+    pub fn query(self: &Arc<Self>, sql: &str) -> Result<Vec<Vec<SQLiteValue>>> {
+        let Some(stmt) = self.prepare(sql)?.prepare_next()? else {
+            return Ok(vec![]);
+        };
+        let mut rows = vec![];
+        while let Some(value) = stmt.get_one()? {
+            rows.extend(value.rows);
+        }
+        Ok(rows)
+    }
+
+    /// Start a dry run, the changes until [`SQLiteDb::end_dry_run`] are rolled back
+    pub fn begin_dry_run(self: &Arc<Self>) -> Result<()> {
+        self.query(&format!(
+            "SAVEPOINT {}",
+            DRY_RUN_SAVEPOINT.to_string_lossy()
+        ))?;
+        unsafe {
+            sqlite3_set_authorizer(self.sqlite3, Some(dry_run_authorizer), std::ptr::null_mut());
+        }
+        Ok(())
+    }
+
+    /// Roll back the changes since [`SQLiteDb::begin_dry_run`]
+    pub fn end_dry_run(self: &Arc<Self>) -> Result<()> {
+        unsafe {
+            sqlite3_set_authorizer(self.sqlite3, None, std::ptr::null_mut());
+        }
+        let name = DRY_RUN_SAVEPOINT.to_string_lossy();
+        self.query(&format!("ROLLBACK TO {name}"))?;
+        self.query(&format!("RELEASE {name}"))?;
+        Ok(())
+    }
+
+    /// Attach the database at `uri` as the schema `alias`
+    pub fn attach(self: &Arc<Self>, uri: &str, alias: &str) -> Result<()> {
+        let sql = format!(
+            "ATTACH DATABASE {} AS {}",
+            SQLiteValue::Text(uri.into()),
+            identifier(alias)
+        );
+        self.query(&sql)?;
+        Ok(())
+    }
+
+    pub fn detach(self: &Arc<Self>, alias: &str) -> Result<()> {
+        self.query(&format!("DETACH DATABASE {}", identifier(alias)))?;
+        Ok(())
     }
 
     pub fn prepare(self: &Arc<Self>, sql: &str) -> Result<SQLiteStatements> {
@@ -118,14 +240,31 @@ impl SQLiteStatements {
         }))
     }
 
-    pub fn stmts_result(self) -> Result<Vec<SQLiteStatementResult>> {
-        let mut result = vec![];
-        for stmt in self {
-            let stmt = stmt?;
-            result.push(stmt.pack(stmt.get_all()?));
+    /// Skip the next statement, e.g. if it failed to prepare
+    ///
+    /// Returns the position of the skipped statement.
+    pub fn skip_next(&mut self) -> Result<[usize; 2]> {
+        if self.tail.is_null() {
+            let end = self.sql.as_bytes().len();
+            return Ok([end, end]);
+        }
+
+        let start_offset = self.tail as usize - self.sql.as_ptr() as usize;
+        let rest = &self.sql.as_bytes()[start_offset..];
+        let skipped = rest.len() - rest.trim_ascii_start().len();
+
+        // the statement ends at the first semicolon that completes it
+        let mut len = rest.len();
+        for (idx, _) in rest.iter().enumerate().filter(|(_, c)| **c == b';') {
+            let stmt = CString::new(&rest[..=idx]).map_err(|_| SQLitendError::ToCStr)?;
+            if unsafe { sqlite3_complete(stmt.as_ptr()) } != 0 {
+                len = idx + 1;
+                break;
+            }
         }
-        result.push(SQLiteStatementResult::Finish);
-        Ok(result)
+
+        self.tail = unsafe { self.tail.add(len) };
+        Ok([start_offset + skipped, start_offset + len])
     }
 }
 
@@ -158,28 +297,264 @@ impl SQLitePreparedStatement {
         }
     }
 
-    pub fn pack(&self, values: Option<SQLiteStatementValues>) -> SQLiteStatementResult {
-        let values = SQLiteStatementTable {
+    pub fn position(&self) -> [usize; 2] {
+        self.position
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// Names of the parameters by index, a bare `?` is named `?N`
+    pub fn parameters(&self) -> Vec<String> {
+        let count = unsafe { sqlite3_bind_parameter_count(self.stmt) };
+        (1..=count)
+            .map(|idx| {
+                let name = unsafe { sqlite3_bind_parameter_name(self.stmt, idx) };
+                if name.is_null() {
+                    format!("?{idx}")
+                } else {
+                    unsafe { CStr::from_ptr(name).to_string_lossy().to_string() }
+                }
+            })
+            .collect()
+    }
+
+    /// Bind the parameters by name, the others stay NULL
+    pub fn bind(&self, parameters: &[SQLiteParameter]) -> Result<()> {
+        for (idx, name) in (1..).zip(self.parameters()) {
+            let Some(param) = parameters.iter().find(|param| param.name == name) else {
+                continue;
+            };
+            let invalid = || {
+                SQLitendError::Bind(format!(
+                    "{name}: {:?} is not a valid {}",
+                    param.value,
+                    param.kind.value().to_lowercase()
+                ))
+            };
+            let value = param.value.trim();
+            let ret = match param.kind {
+                ParameterKind::Integer => {
+                    let value = value.parse::<i64>().map_err(|_| invalid())?;
+                    unsafe { sqlite3_bind_int64(self.stmt, idx, value) }
+                }
+                ParameterKind::Real => {
+                    let value = value.parse::<f64>().map_err(|_| invalid())?;
+                    unsafe { sqlite3_bind_double(self.stmt, idx, value) }
+                }
+                ParameterKind::Text => unsafe {
+                    sqlite3_bind_text(
+                        self.stmt,
+                        idx,
+                        param.value.as_ptr().cast(),
+                        c_int::try_from(param.value.len()).map_err(|_| invalid())?,
+                        SQLITE_TRANSIENT(),
+                    )
+                },
+                ParameterKind::Blob => {
+                    let value = hex::decode(value).map_err(|_| invalid())?;
+                    unsafe {
+                        sqlite3_bind_blob(
+                            self.stmt,
+                            idx,
+                            value.as_ptr().cast(),
+                            c_int::try_from(value.len()).map_err(|_| invalid())?,
+                            SQLITE_TRANSIENT(),
+                        )
+                    }
+                }
+                ParameterKind::Null => unsafe { sqlite3_bind_null(self.stmt, idx) },
+            };
+            if ret != SQLITE_OK {
+                let error = sqlite_err(ret, self.sqlite3);
+                return Err(SQLitendError::Bind(format!("{name}: {}", error.message)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind the values in order, starting from the first parameter
+    pub fn bind_values(&self, values: &[SQLiteValue]) -> Result<()> {
+        for (idx, value) in (1..).zip(values) {
+            let too_large = || SQLitendError::Bind(format!("?{idx}: the value is too large"));
+            let ret = match value {
+                SQLiteValue::Null => unsafe { sqlite3_bind_null(self.stmt, idx) },
+                SQLiteValue::Integer(number) => unsafe {
+                    sqlite3_bind_int64(self.stmt, idx, *number)
+                },
+                SQLiteValue::Real(number) => unsafe {
+                    sqlite3_bind_double(self.stmt, idx, *number)
+                },
+                SQLiteValue::Text(text) => unsafe {
+                    sqlite3_bind_text(
+                        self.stmt,
+                        idx,
+                        text.as_ptr().cast(),
+                        c_int::try_from(text.len()).map_err(|_| too_large())?,
+                        SQLITE_TRANSIENT(),
+                    )
+                },
+                SQLiteValue::Blob(blob) => unsafe {
+                    sqlite3_bind_blob(
+                        self.stmt,
+                        idx,
+                        blob.as_ptr().cast(),
+                        c_int::try_from(blob.len()).map_err(|_| too_large())?,
+                        SQLITE_TRANSIENT(),
+                    )
+                },
+            };
+            if ret != SQLITE_OK {
+                let error = sqlite_err(ret, self.sqlite3);
+                return Err(SQLitendError::Bind(format!("?{idx}: {}", error.message)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Step through all lines without keeping them, then reset the statement to be bound again
+    pub fn execute(&self) -> Result<()> {
+        while self.step()? {}
+        unsafe {
+            sqlite3_reset(self.stmt);
+        }
+        Ok(())
+    }
+
+    /// Step through `EXPLAIN` (mode 1) or `EXPLAIN QUERY PLAN` (mode 2) instead of the statement
+    pub fn set_explain(&self, mode: c_int) -> Result<()> {
+        let ret = unsafe { sqlite3_stmt_explain(self.stmt, mode) };
+        if ret != SQLITE_OK {
+            return Err(SQLitendError::Explain(sqlite_err(ret, self.sqlite3)));
+        }
+        Ok(())
+    }
+
+    /// Get the rows of `EXPLAIN` (mode 1) or `EXPLAIN QUERY PLAN` (mode 2)
+    ///
+    /// The statement itself is not executed.
+    pub fn explain(&self, mode: c_int) -> Result<Option<SQLiteStatementValues>> {
+        self.set_explain(mode)?;
+
+        let mut values: Option<SQLiteStatementValues> = None;
+        while let Some(value) = self.get_one()? {
+            match &mut values {
+                Some(values) => values.rows.extend(value.rows),
+                None => values = Some(value),
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn pack(&self, values: Option<SQLiteStatementValues>) -> SQLiteStatementTable {
+        SQLiteStatementTable {
             sql: self.sql.clone(),
             position: self.position,
             values,
-        };
-        SQLiteStatementResult::Step(values)
+            metrics: None,
+        }
     }
 
-    pub fn get_all(&self) -> Result<Option<SQLiteStatementValues>> {
-        let mut values = match self.get_one()? {
-            Some(value) => value,
-            None => return Ok(None),
-        };
+    fn status(&self, op: c_int) -> i32 {
+        unsafe { sqlite3_stmt_status(self.stmt, op, 0) }
+    }
+
+    /// Step through all lines and hand them over in chunks
+    ///
+    /// A chunk is handed over once it has `max_rows` rows or `max_millis` have passed.
+    /// The time spent in `f` is not part of the returned duration.
+    pub fn get_chunks<F>(
+        &self,
+        max_rows: usize,
+        max_millis: f64,
+        mut f: F,
+    ) -> Result<SQLiteStatementMetrics>
+    where
+        F: FnMut(SQLiteStatementValues),
+    {
+        let total_changes = unsafe { sqlite3_total_changes64(self.sqlite3) };
+        let last_insert_rowid = unsafe { sqlite3_last_insert_rowid(self.sqlite3) };
+
+        let mut chunk: Option<SQLiteStatementValues> = None;
+        let mut sent = false;
+        let now = clock();
+        let start = now();
+        let mut since = start;
+        let mut handing = 0.0;
 
         while let Some(value) = self.get_one()? {
-            for row in value.rows {
-                values.rows.push(row);
+            let values = chunk.get_or_insert_with(|| SQLiteStatementValues {
+                columns: value.columns,
+                rows: vec![],
+            });
+            values.rows.extend(value.rows);
+
+            let before = now();
+            if values.rows.len() >= max_rows || before - since >= max_millis {
+                f(chunk.take().unwrap());
+                sent = true;
+                since = now();
+                handing += since - before;
+            }
+        }
+
+        let duration = now() - start - handing;
+
+        if let Some(values) = chunk {
+            f(values);
+        } else if !sent {
+            // a query without rows still has its columns
+            let columns = self.columns()?;
+            if !columns.is_empty() {
+                f(SQLiteStatementValues {
+                    columns,
+                    rows: vec![],
+                });
             }
         }
 
-        Ok(Some(values))
+        // sqlite3_changes64 keeps the value of the last INSERT, UPDATE or DELETE
+        let changed = unsafe { sqlite3_total_changes64(self.sqlite3) } != total_changes;
+        let inserted = unsafe { sqlite3_last_insert_rowid(self.sqlite3) };
+
+        Ok(SQLiteStatementMetrics {
+            duration,
+            changes: if changed {
+                unsafe { sqlite3_changes64(self.sqlite3) }
+            } else {
+                0
+            },
+            last_insert_rowid: (inserted != last_insert_rowid).then_some(inserted),
+            full_scan_steps: self.status(SQLITE_STMTSTATUS_FULLSCAN_STEP),
+            sorts: self.status(SQLITE_STMTSTATUS_SORT),
+            autoindexes: self.status(SQLITE_STMTSTATUS_AUTOINDEX),
+            vm_steps: self.status(SQLITE_STMTSTATUS_VM_STEP),
+        })
+    }
+
+    fn column_name(&self, col_ndx: c_int) -> Result<&str> {
+        unsafe {
+            let ptr = sqlite3_column_name(self.stmt, col_ndx);
+            if ptr.is_null() {
+                return Err(SQLitendError::GetColumnName(
+                    "the column name is a null pointer, this shouldn't happen".into(),
+                ));
+            }
+            CStr::from_ptr(ptr).to_str().map_err(|_| {
+                SQLitendError::GetColumnName(
+                    "the column name is not a string, this shouldn't happen".into(),
+                )
+            })
+        }
+    }
+
+    /// Names of the columns of the statement, empty if it returns no rows.
+    pub fn columns(&self) -> Result<Vec<String>> {
+        let column_count = unsafe { sqlite3_column_count(self.stmt) };
+        (0..column_count)
+            .map(|col_ndx| self.column_name(col_ndx).map(str::to_string))
+            .collect()
     }
 
     /// Get data for all columns of the current row
@@ -195,30 +570,17 @@ impl SQLitePreparedStatement {
 
         for col_ndx in 0..column_count {
             // column_name as key
-            let (column_name, column_type) = unsafe {
-                let ptr = sqlite3_column_name(self.stmt, col_ndx);
-                if ptr.is_null() {
-                    return Err(SQLitendError::GetColumnName(
-                        "the column name is a null pointer, this shouldn't happen".into(),
-                    ));
-                }
-                let Ok(column_name) = CStr::from_ptr(ptr).to_str() else {
-                    return Err(SQLitendError::GetColumnName(
-                        "the column name is not a string, this shouldn't happen".into(),
-                    ));
-                };
-                (column_name, sqlite3_column_type(self.stmt, col_ndx))
-            };
+            let column_name = self.column_name(col_ndx)?;
+            let column_type = unsafe { sqlite3_column_type(self.stmt, col_ndx) };
 
             // https://www.sqlite.org/c3ref/column_blob.html
             let value = unsafe {
                 match column_type {
-                    SQLITE_NULL => "NULL".to_string(),
+                    SQLITE_NULL => SQLiteValue::Null,
                     SQLITE_INTEGER => {
-                        let number = sqlite3_column_int64(self.stmt, col_ndx);
-                        number.to_string()
+                        SQLiteValue::Integer(sqlite3_column_int64(self.stmt, col_ndx))
                     }
-                    SQLITE_FLOAT => sqlite3_column_double(self.stmt, col_ndx).to_string(),
+                    SQLITE_FLOAT => SQLiteValue::Real(sqlite3_column_double(self.stmt, col_ndx)),
                     SQLITE_TEXT => {
                         let slice = {
                             let text = sqlite3_column_text(self.stmt, col_ndx);
@@ -230,7 +592,7 @@ impl SQLitePreparedStatement {
                         let Ok(text) = std::str::from_utf8(slice) else {
                             return Err(SQLitendError::Utf8Text);
                         };
-                        format!("'{text}'")
+                        SQLiteValue::Text(text.into())
                     }
                     SQLITE_BLOB => {
                         let slice = {
@@ -238,8 +600,7 @@ impl SQLitePreparedStatement {
                             let len = sqlite3_column_bytes(self.stmt, col_ndx);
                             std::slice::from_raw_parts(blob.cast::<u8>(), len as usize)
                         };
-                        let hex = hex::encode(slice);
-                        format!("x'{hex}'")
+                        SQLiteValue::Blob(slice.to_vec())
                     }
                     _ => return Err(SQLitendError::UnsupportColumnType(column_type)),
                 }